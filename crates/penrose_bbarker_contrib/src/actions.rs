@@ -0,0 +1,48 @@
+use penrose::{
+    builtin::actions::key_handler,
+    core::{bindings::KeyEventHandler, State},
+    util::spawn_with_args,
+    x::XConn,
+};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::{process::descendant_cwd, workspaces::client_pids};
+
+/// Spawn `command` with `{cwd}` replaced by the working directory of the
+/// focused window, e.g. `"alacritty --working-directory {cwd}"`. For a
+/// terminal that is the directory of the shell running inside it. Falls back
+/// to `$HOME` when nothing is focused or the directory can't be found.
+pub fn spawn_in_focused_cwd<X>(command: impl Into<String>) -> Box<dyn KeyEventHandler<X>>
+where
+    X: XConn,
+{
+    let command = command.into();
+
+    key_handler(move |state: &mut State<X>, x: &X| {
+        let cwd = state
+            .client_set
+            .current_client()
+            .and_then(|&id| client_pids(x, id)?.first().copied())
+            .and_then(|pid| descendant_cwd(Path::new("/proc"), pid))
+            .or_else(|| env::var_os("HOME").map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from("/"));
+        let cwd = cwd.to_string_lossy();
+
+        // Split before substituting so that a directory with spaces in its
+        // name stays one argument
+        let args: Vec<String> = command
+            .split_whitespace()
+            .map(|arg| arg.replace("{cwd}", &cwd))
+            .collect();
+        match args.split_first() {
+            Some((program, args)) => spawn_with_args(
+                program.as_str(),
+                &args.iter().map(String::as_str).collect::<Vec<_>>(),
+            ),
+            None => Ok(()),
+        }
+    })
+}
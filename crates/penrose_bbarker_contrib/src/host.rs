@@ -0,0 +1,19 @@
+use once_cell::sync::Lazy;
+use std::{env, fs, process::Command};
+
+pub fn get_hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| {
+            Command::new("hostname")
+                .output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        })
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| "Unknown".to_string())
+}
+
+// Could possibly use alternatives from the nix crate
+pub static HOSTNAME: Lazy<String> =
+    Lazy::new(|| env::var("HOSTNAME").unwrap_or_else(|_| get_hostname()));
+pub static USERNAME: Lazy<String> =
+    Lazy::new(|| env::var("USER").unwrap_or_else(|_| "Unknown".to_string()));
@@ -2,36 +2,33 @@
 #![allow(clippy::unit_arg)]
 #![warn(future_incompatible, rust_2024_compatibility)]
 
+pub mod actions;
+pub mod host;
 pub mod log;
 pub mod menus;
+pub mod outputs;
+pub mod process;
+pub mod process_cache;
+pub mod remote;
+pub mod rules;
+pub mod services;
+pub mod session;
+pub mod status;
+pub mod swallow;
+#[cfg(test)]
+mod testing;
+pub mod tray;
 pub mod workspaces;
-use std::process::Command;
+
+pub use host::HOSTNAME;
+pub use process::{is_in_path, is_running};
 
 use once_cell::sync::Lazy;
 use penrose::{core::bindings::KeyEventHandler, x11rb::RustConn};
-use sysinfo::System;
+use process_cache::ProcessCache;
 // #![deny(unused_crate_dependencies)]
 
-pub static SYSTEM: Lazy<System> = Lazy::new(System::new_all);
-
-pub type KeyHandler = Box<dyn KeyEventHandler<RustConn>>;
-
-pub fn is_running(program: &str) -> bool {
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg(format!("ps -ef | grep {} | grep -v grep", program))
-        .output()
-        .unwrap();
-
-    !output.stdout.is_empty()
-}
-
-pub fn is_in_path(program: &str) -> bool {
-    let output = Command::new("bash")
-        .arg("-c")
-        .arg(format!("type {}", program))
-        .output()
-        .unwrap();
+/// The process table shared by the bar, menus and window rules.
+pub static SYSTEM: Lazy<ProcessCache> = Lazy::new(ProcessCache::new);
 
-    output.status.success()
-}
+pub type KeyHandler<X = RustConn> = Box<dyn KeyEventHandler<X>>;
@@ -0,0 +1,322 @@
+use penrose::{
+    extensions::util::dmenu::{DMenu, DMenuConfig, DMenuKind, MenuMatch},
+    Error, Result,
+};
+use serde::Deserialize;
+use std::{
+    env, fmt,
+    fs::{self, DirBuilder},
+    io::{Read, Write},
+    os::unix::fs::DirBuilderExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The `WM_CLASS` of the terminal window that [FzfPopup] runs in, for
+/// floating it.
+pub const FZF_POPUP_CLASS: &str = "menu-popup";
+
+/// How a menu should be shown.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MenuOptions {
+    pub prompt: Option<String>,
+    pub ignore_case: bool,
+    /// The screen to show the menu on, where the backend can choose
+    pub screen_index: usize,
+    /// Allow more than one entry to be picked
+    pub multi_select: bool,
+    pub show_on_bottom: bool,
+}
+
+/// A program that asks the user to pick from a list of entries.
+pub trait MenuBackend: fmt::Debug + Send + Sync {
+    /// Show `entries` and return what was picked: nothing if the menu was
+    /// dismissed and at most one line unless `multi_select` is set. Typed
+    /// text that isn't one of the entries is returned as it was typed.
+    fn choose(&self, options: &MenuOptions, entries: &[String]) -> Result<Vec<String>>;
+}
+
+/// The [MenuBackend]s that can be picked in a config file: the bundled ones
+/// by name, or any other program as `{ custom = { command, args } }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MenuKind {
+    #[default]
+    DmenuRs,
+    Dmenu,
+    Rofi,
+    Fzf,
+    Custom {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+impl MenuKind {
+    pub fn backend(&self) -> Arc<dyn MenuBackend> {
+        match self {
+            MenuKind::DmenuRs => Arc::new(Dmenu {
+                kind: DMenuKind::Rust,
+            }),
+            MenuKind::Dmenu => Arc::new(Dmenu {
+                kind: DMenuKind::Suckless,
+            }),
+            MenuKind::Rofi => Arc::new(Rofi),
+            MenuKind::Fzf => Arc::new(FzfPopup),
+            MenuKind::Custom { command, args } => Arc::new(Custom {
+                command: command.clone(),
+                args: args.clone(),
+            }),
+        }
+    }
+}
+
+/// dmenu, either the suckless original or dmenu-rs, run through penrose's own
+/// helper. Several entries can be picked with Ctrl-Return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dmenu {
+    pub kind: DMenuKind,
+}
+
+impl MenuBackend for Dmenu {
+    fn choose(&self, options: &MenuOptions, entries: &[String]) -> Result<Vec<String>> {
+        let config = DMenuConfig {
+            show_on_bottom: options.show_on_bottom,
+            ignore_case: options.ignore_case,
+            kind: self.kind,
+            custom_prompt: options.prompt.clone(),
+            ..Default::default()
+        };
+
+        // Several picks don't match a single entry, so come back as input
+        let output = match DMenu::new(&config, options.screen_index).build_menu(entries.to_vec())? {
+            MenuMatch::Line(_, line) => line,
+            MenuMatch::UserInput(input) => input,
+            MenuMatch::NoMatch => String::new(),
+        };
+
+        Ok(picked(&output, options.multi_select))
+    }
+}
+
+/// `rofi -dmenu`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rofi;
+
+impl MenuBackend for Rofi {
+    fn choose(&self, options: &MenuOptions, entries: &[String]) -> Result<Vec<String>> {
+        let mut args = vec![
+            "-dmenu".to_string(),
+            "-m".to_string(),
+            options.screen_index.to_string(),
+            "-l".to_string(),
+            "10".to_string(),
+        ];
+        if options.show_on_bottom {
+            // Bottom center
+            args.extend(["-location".to_string(), "6".to_string()]);
+        }
+        if options.ignore_case {
+            args.push("-i".to_string());
+        }
+        if options.multi_select {
+            args.push("-multi-select".to_string());
+        }
+        if let Some(prompt) = &options.prompt {
+            args.extend(["-p".to_string(), prompt.clone()]);
+        }
+
+        let output = pipe_through("rofi", &args, entries)?;
+
+        Ok(picked(&output, options.multi_select))
+    }
+}
+
+/// fzf in an alacritty window with the class [FZF_POPUP_CLASS], with Tab
+/// picking several entries. Unlike the other backends this is a managed
+/// window, so it can't be shown from the window manager's own thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FzfPopup;
+
+impl MenuBackend for FzfPopup {
+    fn choose(&self, options: &MenuOptions, entries: &[String]) -> Result<Vec<String>> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos());
+        let runtime_dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, PathBuf::from);
+        let dir = runtime_dir.join(format!("penrose-menu-{}-{nanos}", std::process::id()));
+        // Only we may put anything in here, and a directory that someone else
+        // made first is an error, so nobody else can choose what is picked
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let (input, output) = (dir.join("entries"), dir.join("picked"));
+        fs::write(&input, entries.join("\n"))?;
+
+        let mut fzf_args = vec![
+            format!("--prompt={}", options.prompt.as_deref().unwrap_or("> ")),
+            if options.ignore_case { "-i" } else { "+i" }.to_string(),
+            if options.multi_select {
+                "--multi"
+            } else {
+                "--no-multi"
+            }
+            .to_string(),
+        ];
+        if !options.show_on_bottom {
+            fzf_args.push("--layout=reverse".to_string());
+        }
+
+        // The entries and picks go through files as the terminal, rather
+        // than fzf, is our child
+        let mut child = Command::new("alacritty")
+            .args(["--class", FZF_POPUP_CLASS, "-e", "sh", "-c"])
+            .arg(r#"in="$1"; out="$2"; shift 2; fzf "$@" < "$in" > "$out""#)
+            .arg("fzf-menu")
+            .arg(&input)
+            .arg(&output)
+            .args(&fzf_args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // SIGCHLD is ignored by the window manager, so the terminal is reaped
+        // for us and waiting on it errors once it has gone
+        while let Ok(None) = child.try_wait() {
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        let result = fs::read_to_string(&output).unwrap_or_default();
+        fs::remove_dir_all(&dir)?;
+
+        Ok(picked(&result, options.multi_select))
+    }
+}
+
+/// Any other program that reads entries on its stdin and prints those picked,
+/// one per line. `{prompt}` and `{screen}` in its arguments are replaced with
+/// the prompt and screen index.
+///
+/// ```toml
+/// menu = { custom = { command = "bemenu", args = ["-p", "{prompt}", "-m", "{screen}"] } }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Custom {
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+impl MenuBackend for Custom {
+    fn choose(&self, options: &MenuOptions, entries: &[String]) -> Result<Vec<String>> {
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| {
+                arg.replace("{prompt}", options.prompt.as_deref().unwrap_or_default())
+                    .replace("{screen}", &options.screen_index.to_string())
+            })
+            .collect();
+
+        let output = pipe_through(&self.command, &args, entries)?;
+
+        Ok(picked(&output, options.multi_select))
+    }
+}
+
+/// Run `program` with `entries` on its stdin, one per line, returning what it
+/// printed.
+fn pipe_through(program: &str, args: &[String], entries: &[String]) -> Result<String> {
+    let mut proc = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    {
+        let mut stdin = proc
+            .stdin
+            .take()
+            .ok_or_else(|| Error::Custom(format!("unable to open stdin for {program}")))?;
+        stdin.write_all(entries.join("\n").as_bytes())?;
+    }
+
+    let mut output = String::new();
+    proc.stdout
+        .take()
+        .ok_or_else(|| Error::Custom(format!("unable to read the output of {program}")))?
+        .read_to_string(&mut output)?;
+
+    Ok(output)
+}
+
+/// The non-empty lines of a menu's output.
+fn picked(output: &str, multi_select: bool) -> Vec<String> {
+    let lines = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from);
+
+    if multi_select {
+        lines.collect()
+    } else {
+        lines.take(1).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_kinds_are_named_or_custom() {
+        let kinds: Vec<MenuKind> =
+            serde_json::from_str(r#"["dmenu_rs", "rofi", { "custom": { "command": "bemenu" } }]"#)
+                .unwrap();
+
+        assert_eq!(
+            kinds,
+            vec![
+                MenuKind::DmenuRs,
+                MenuKind::Rofi,
+                MenuKind::Custom {
+                    command: "bemenu".to_string(),
+                    args: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_menu_gets_the_prompt_and_screen_in_its_args() {
+        let menu = Custom {
+            command: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "cat; echo; echo \"$0 on $1\"".to_string(),
+                "{prompt}".to_string(),
+                "{screen}".to_string(),
+            ],
+        };
+        let options = MenuOptions {
+            prompt: Some("pick>".to_string()),
+            screen_index: 2,
+            multi_select: true,
+            ..Default::default()
+        };
+        let entries = vec!["one".to_string(), "two".to_string()];
+
+        let picked = menu.choose(&options, &entries).unwrap();
+
+        assert_eq!(picked, vec!["one", "two", "pick> on 2"]);
+    }
+
+    #[test]
+    fn only_the_first_line_is_picked_without_multi_select() {
+        assert_eq!(picked("\n one \ntwo\n", false), vec!["one"]);
+        assert_eq!(picked("one\n\ntwo\n", true), vec!["one", "two"]);
+        assert!(picked("", true).is_empty());
+    }
+}
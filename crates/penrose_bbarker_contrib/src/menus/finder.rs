@@ -1,33 +1,133 @@
 use once_cell::sync::Lazy;
 use penrose::builtin::actions::key_handler;
 use penrose::core::State;
-use penrose::extensions::util::dmenu::{DMenu, DMenuConfig, DMenuKind, MenuMatch};
-use penrose::x11rb::RustConn;
+use penrose::x::{XConn, XConnExt};
+use penrose::Result;
 
-use std::env;
-use std::process::Command;
+use std::sync::Arc;
+use std::thread;
 
-use crate::workspaces::workspace_app_info;
+use super::backend::{MenuBackend, MenuKind, MenuOptions};
+// Kept here for those who used them before they had a module of their own
+pub use crate::host::{get_hostname, HOSTNAME, USERNAME};
+use crate::log::LogPenroseError;
+use crate::remote::RemoteSender;
+use crate::workspaces::{workspace_clients, ClientInfo, WorkspaceClients};
 use crate::{KeyHandler, SYSTEM};
 
+/// The [MenuBackend] used by the menus here, kept as a window manager
+/// extension so that it can be swapped when the config is reloaded. Without
+/// one, dmenu-rs is used.
+#[derive(Debug, Clone)]
+pub struct SelectedMenu(pub Arc<dyn MenuBackend>);
+
+/// Replacements made to executable names and window titles before they are
+/// shown by [goto_workspace_by_apps], as `(from, to)` pairs applied in order.
 #[derive(Clone, Debug, Default)]
 pub struct GotoWorkspaceConfig<'a> {
-    name_substitutions: Vec<(&'a str, &'a str)>,
-    title_substitutions: Vec<(&'a str, &'a str)>,
+    pub name_substitutions: Vec<(&'a str, &'a str)>,
+    pub title_substitutions: Vec<(&'a str, &'a str)>,
 }
 
-pub fn get_hostname() -> String {
-    Command::new("hostname")
-        .output()
-        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
-        .unwrap_or_else(|_| "Unknown".to_string())
+impl GotoWorkspaceConfig<'_> {
+    /// How a client is shown in the menu: "app ➥ title".
+    pub fn client_entry(&self, client: &ClientInfo) -> String {
+        let substitute = |s: &str, subs: &[(&str, &str)]| {
+            subs.iter()
+                .fold(s.to_string(), |s, (rep, sub)| s.replace(rep, sub))
+        };
+        let app = substitute(
+            client.exe.as_deref().unwrap_or_default(),
+            &self.name_substitutions,
+        );
+        let title = substitute(&client.title, &self.title_substitutions);
+
+        format!("{} ➥ {}", app.trim(), title.trim())
+    }
+
+    /// One "tag: app ➥ title | ..." line per occupied workspace, with numbered
+    /// tags first in numeric order.
+    pub fn workspace_entries(
+        &self,
+        workspaces: impl IntoIterator<Item = WorkspaceClients>,
+    ) -> Vec<String> {
+        let mut entries: Vec<(String, String)> = workspaces
+            .into_iter()
+            .filter(|ws| !ws.clients.is_empty())
+            .map(|ws| {
+                let clients = ws
+                    .clients
+                    .iter()
+                    .map(|client| self.client_entry(client))
+                    .collect::<Vec<String>>()
+                    .join(" | ");
+                (ws.tag, clients)
+            })
+            .collect();
+        entries.sort_by_key(|(tag, _)| tag.parse::<u16>().unwrap_or(999));
+
+        entries
+            .into_iter()
+            .map(|(tag, clients)| format!("{tag}: {clients}"))
+            .collect()
+    }
+}
+
+/// The tag at the start of a line from [GotoWorkspaceConfig::workspace_entries].
+pub fn extract_tag(entry: &str) -> Option<&str> {
+    entry.split_once(':').map(|(tag, _)| tag.trim())
+}
+
+/// Show `entries` with the [SelectedMenu] on the focused screen, then run
+/// `on_choice` with those picked that are one of `entries`. With a
+/// [RemoteSender] among the extensions the menu is shown from another thread
+/// so that the window manager keeps running, which menus in a managed window
+/// such as fzf need.
+fn show_menu<X, F>(
+    mut options: MenuOptions,
+    entries: Vec<String>,
+    state: &mut State<X>,
+    x: &X,
+    on_choice: F,
+) -> Result<()>
+where
+    X: XConn + 'static,
+    F: FnOnce(Vec<String>, &mut State<X>, &X) -> Result<()> + Send + 'static,
+{
+    options.screen_index = state.client_set.current_screen().index();
+    let backend = state
+        .extension::<SelectedMenu>()
+        .map_or_else(|_| MenuKind::default().backend(), |m| m.borrow().0.clone());
+    let pick = move || -> Result<Vec<String>> {
+        let picked = backend.choose(&options, &entries)?;
+        Ok(picked.into_iter().filter(|p| entries.contains(p)).collect())
+    };
+
+    let remote = state
+        .extension::<RemoteSender<X>>()
+        .ok()
+        .map(|remote| remote.borrow().clone());
+
+    match remote {
+        Some(remote) => {
+            thread::spawn(move || {
+                if let Some(picked) = pick().log_err("Couldn't show a menu") {
+                    if !picked.is_empty() {
+                        remote
+                            .send(Box::new(move |state, x| on_choice(picked, state, x)))
+                            .log_err("Couldn't act on a menu choice");
+                    }
+                }
+            });
+            Ok(())
+        }
+        None => match pick()? {
+            picked if picked.is_empty() => Ok(()),
+            picked => on_choice(picked, state, x),
+        },
+    }
 }
 
-// Could possibly use alternatives from the nix crate
-pub static HOSTNAME: Lazy<String> =
-    Lazy::new(|| env::var("HOSTNAME").unwrap_or_else(|_| get_hostname()));
-pub static USERNAME: Lazy<String> =
-    Lazy::new(|| env::var("USER").unwrap_or_else(|_| "Unknown".to_string()));
 pub static NU_SHELL_LOC: Lazy<String> = Lazy::new(|| format!("{}@{}:", *USERNAME, *HOSTNAME));
 
 /// My Config; I've left it here as an example. Though you could use it, you may
@@ -39,132 +139,142 @@ pub static GOTO_WS_CONFIG: Lazy<GotoWorkspaceConfig> = Lazy::new(|| GotoWorkspac
 
 /// Navigate to a workspace by typing part of a process name
 /// or window title running on the workspace.
-pub fn goto_workspace_by_apps(
+pub fn goto_workspace_by_apps<X>(
     conf: &'static GotoWorkspaceConfig<'static>,
-) -> Box<dyn Fn() -> KeyHandler + Send + Sync> {
-    fn extract_tag(str: &str) -> Option<&str> {
-        let parts: Vec<_> = str.splitn(2, ':').collect();
-        if parts.len() == 2 {
-            parts.first().map(|part| part.trim())
-        } else {
-            None
-        }
-    }
-
+) -> Box<dyn Fn() -> KeyHandler<X> + Send + Sync>
+where
+    X: XConn + 'static,
+{
     Box::new(|| {
-        key_handler(|state: &mut State<RustConn>, xcon: &RustConn| {
-            let conf_local = conf.clone();
-            let sc_ix = state.client_set.current_screen().index();
-            let dmenu = DMenu::new(
-                &DMenuConfig {
-                    kind: DMenuKind::Rust,
-                    custom_prompt: Some("workspace> ".to_string()),
-                    ignore_case: true,
-                    ..Default::default()
-                },
-                sc_ix,
+        key_handler(|state: &mut State<X>, xcon: &X| {
+            let entries = conf.workspace_entries(
+                state
+                    .client_set
+                    .ordered_workspaces()
+                    .map(|ws| workspace_clients(&SYSTEM, state, xcon, ws)),
             );
-            let tags_display_strings = {
-                let workspaces = state.client_set.workspaces();
-                let mut unsorted_tds: Vec<(String, String)> = workspaces
-                    .map(|ws| {
-                        let ws_app_info = workspace_app_info(&SYSTEM, state, xcon, ws);
-                        let window_titles = ws_app_info
-                            .titles
-                            .into_iter()
-                            .map(|title| {
-                                conf_local
-                                    .title_substitutions
-                                    .iter()
-                                    .fold(title, |new_title, (rep, sub)| {
-                                        new_title.replace(rep, sub)
-                                    })
-                                    .trim()
-                                    .to_owned()
-                            })
-                            .collect::<Vec<String>>();
-                        let app_names = ws_app_info.processes.into_iter().map(|exe_name| {
-                            conf_local
-                                .name_substitutions
-                                .iter()
-                                .fold(exe_name, |en, (rep, sub)| en.replace(rep, sub))
-                                .trim()
-                                .to_owned()
-                        });
-                        let display_string = {
-                            let display_strings = app_names
-                                .into_iter()
-                                .zip(window_titles)
-                                .map(|(app, title)| format!("{app} ➥ {title}"))
-                                .collect::<Vec<String>>();
-                            display_strings.join(" | ")
-                        };
-                        (ws_app_info.tag, display_string)
-                    })
-                    .filter(|(_, display)| !display.is_empty())
-                    .collect();
-                unsorted_tds.sort_by_key(|(tag, _dsp)| tag.parse::<u16>().unwrap_or(999));
-                unsorted_tds // now sorted
+            let options = MenuOptions {
+                prompt: Some("workspace> ".to_string()),
+                ignore_case: true,
+                ..Default::default()
             };
 
-            let entries = tags_display_strings
-                .into_iter()
-                .map(|(tag, display_string)| format!("{}: {}", tag, display_string))
-                .collect();
-            if let Ok(MenuMatch::Line(_, choice)) = dmenu.build_menu(entries) {
-                extract_tag(&choice)
-                    .ok_or(penrose::Error::Custom("No tag for workspace".to_string()))
-                    .map(|tag| state.client_set.focus_tag(tag))
-            } else {
-                Ok(())
-            }
+            show_menu(options, entries, state, xcon, |picked, state, x| {
+                let tag = extract_tag(&picked[0])
+                    .ok_or(penrose::Error::Custom("No tag for workspace".to_string()))?
+                    .to_string();
+                x.modify_and_refresh(state, |cs| cs.focus_tag(&tag))
+            })
         })
     })
 }
 
 /// Got a lot of workspaces? This function, and its sister function,
 /// `send_to_workspace_menu`, can help you navigate to them using
-/// a dmenu.
-pub fn workspace_menu() -> KeyHandler {
-    key_handler(|state, _xcon| {
-        let sc_ix = state.client_set.current_screen().index();
-        let dmenu = DMenu::new(
-            &DMenuConfig {
-                kind: DMenuKind::Rust,
-                custom_prompt: Some("workspace> ".to_string()),
-                ..Default::default()
-            },
-            sc_ix,
-        );
-        let all_tags = state.client_set.ordered_tags();
-        if let Ok(MenuMatch::Line(_, choice)) = dmenu.build_menu(all_tags) {
-            Ok(state.client_set.focus_tag(choice))
-        } else {
-            Ok(())
-        }
+/// a menu.
+pub fn workspace_menu<X: XConn + 'static>() -> KeyHandler<X> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let options = MenuOptions {
+            prompt: Some("workspace> ".to_string()),
+            ..Default::default()
+        };
+        let tags = state.client_set.ordered_tags();
+
+        show_menu(options, tags, state, x, |picked, state, x| {
+            x.modify_and_refresh(state, |cs| cs.focus_tag(&picked[0]))
+        })
     })
 }
 
 /// Got a lot of workspaces? This function, and its sister function,
 /// `workspace_menu`, can help you navigate to them using
-/// a dmenu.
-pub fn send_to_workspace_menu() -> KeyHandler {
-    key_handler(|state, _xcon| {
-        let sc_ix = state.client_set.current_screen().index();
-        let dmenu = DMenu::new(
-            &DMenuConfig {
-                kind: DMenuKind::Rust,
-                show_on_bottom: true,
-                custom_prompt: Some("send to> ".to_string()),
-                ..Default::default()
-            },
-            sc_ix,
-        );
-        let all_tags = state.client_set.ordered_tags();
-        if let Ok(MenuMatch::Line(_, choice)) = dmenu.build_menu(all_tags) {
-            Ok(state.client_set.move_focused_to_tag(choice))
-        } else {
-            Ok(())
-        }
+/// a menu.
+pub fn send_to_workspace_menu<X: XConn + 'static>() -> KeyHandler<X> {
+    key_handler(|state: &mut State<X>, x: &X| {
+        let options = MenuOptions {
+            prompt: Some("send to> ".to_string()),
+            show_on_bottom: true,
+            ..Default::default()
+        };
+        let tags = state.client_set.ordered_tags();
+
+        show_menu(options, tags, state, x, |picked, state, x| {
+            x.modify_and_refresh(state, |cs| cs.move_focused_to_tag(&picked[0]))
+        })
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_cache::ProcessCache, testing::FakeConn};
+    use penrose::{pure::geometry::Rect, Xid};
+
+    fn client(exe: Option<&str>, title: &str) -> ClientInfo {
+        ClientInfo {
+            id: Xid::from(1),
+            title: title.to_string(),
+            instance: String::new(),
+            class: String::new(),
+            role: None,
+            pid: None,
+            exe: exe.map(str::to_string),
+            cmdline: Vec::new(),
+            cwd: None,
+            floating: false,
+            urgent: false,
+        }
+    }
+
+    #[test]
+    fn client_entry_applies_substitutions_in_order() {
+        let conf = GotoWorkspaceConfig {
+            name_substitutions: vec![("-wrapped", " "), ("fire", "ice"), ("ice", "snow")],
+            title_substitutions: vec![("user@host:", "local")],
+        };
+        let entry = conf.client_entry(&client(Some("firefox-wrapped"), " user@host: ~ "));
+
+        assert_eq!(entry, "snowfox ➥ local ~");
+    }
+
+    #[test]
+    fn client_entry_without_an_exe_shows_the_title() {
+        let entry = GotoWorkspaceConfig::default().client_entry(&client(None, "Terminal"));
+
+        assert_eq!(entry, " ➥ Terminal");
+    }
+
+    #[test]
+    fn workspace_entries_skip_empty_workspaces_with_numbers_first() {
+        let x = FakeConn::new(vec![Rect::new(0, 0, 1000, 800)])
+            .with_client(1, &["web"], "Web", 0)
+            .with_client(2, &["ten"], "Ten", 0)
+            .with_client(3, &["two"], "Two", 0)
+            .with_client(4, &["two"], "Other", 0);
+        let mut state = x.state(&["web", "10", "1", "2"]);
+        for (id, tag) in [(1, "web"), (2, "10"), (3, "2"), (4, "2")] {
+            state.client_set.insert(Xid::from(id));
+            state.client_set.move_client_to_tag(&Xid::from(id), tag);
+        }
+
+        let processes = ProcessCache::new();
+        let entries = GotoWorkspaceConfig::default().workspace_entries(
+            state
+                .client_set
+                .ordered_workspaces()
+                .map(|ws| workspace_clients(&processes, &state, &x, ws)),
+        );
+
+        assert_eq!(
+            entries,
+            vec!["2:  ➥ Other |  ➥ Two", "10:  ➥ Ten", "web:  ➥ Web"]
+        );
+    }
+
+    #[test]
+    fn extract_tag_takes_everything_before_the_first_colon() {
+        assert_eq!(extract_tag("web: firefox ➥ x: y"), Some("web"));
+        assert_eq!(extract_tag(" 2 :"), Some("2"));
+        assert_eq!(extract_tag("firefox ➥ no tag"), None);
+    }
+}
@@ -0,0 +1,717 @@
+use penrose::{Error, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::{self, ConnectionExt as _, Crtc, Mode, ModeInfo, Output, SetConfig},
+        xproto::{ConnectionExt as _, Window},
+    },
+    CURRENT_TIME,
+};
+
+/// Used to work out the physical size of the screen, which only matters to
+/// programs that go by the reported DPI.
+const DPI: f64 = 96.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rotation {
+    #[default]
+    Normal,
+    Left,
+    Right,
+    Inverted,
+}
+
+impl Rotation {
+    fn to_randr(self) -> randr::Rotation {
+        match self {
+            Rotation::Normal => randr::Rotation::ROTATE0,
+            Rotation::Left => randr::Rotation::ROTATE90,
+            Rotation::Inverted => randr::Rotation::ROTATE180,
+            Rotation::Right => randr::Rotation::ROTATE270,
+        }
+    }
+
+    fn is_sideways(self) -> bool {
+        matches!(self, Rotation::Left | Rotation::Right)
+    }
+}
+
+/// How a monitor should be set up when it's connected, in the style of
+/// `xrandr --output`.
+///
+/// ```toml
+/// [[outputs]]
+/// name = "DP-2"
+/// rotate = "left"
+/// primary = true
+///
+/// [[outputs]]
+/// name = "DP-0"
+/// rotate = "left"
+/// right_of = "DP-2"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// The RandR output name, as listed by `xrandr`
+    pub name: String,
+    /// Set to false to turn the output off
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// "WIDTHxHEIGHT", defaulting to the monitor's preferred mode
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub rotate: Rotation,
+    #[serde(default)]
+    pub primary: bool,
+    /// Top left corner in pixels. Without this or one of the relative
+    /// positions the output goes to the right of those listed before it.
+    #[serde(default)]
+    pub position: Option<(i32, i32)>,
+    #[serde(default)]
+    pub left_of: Option<String>,
+    #[serde(default)]
+    pub right_of: Option<String>,
+    #[serde(default)]
+    pub above: Option<String>,
+    #[serde(default)]
+    pub below: Option<String>,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Where an output goes relative to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placement<'a> {
+    At(i32, i32),
+    LeftOf(&'a str),
+    RightOf(&'a str),
+    Above(&'a str),
+    Below(&'a str),
+    AfterPrevious,
+}
+
+impl OutputConfig {
+    fn placement(&self) -> Result<Placement<'_>> {
+        let placements: Vec<Placement<'_>> = [
+            self.position.map(|(x, y)| Placement::At(x, y)),
+            self.left_of.as_deref().map(Placement::LeftOf),
+            self.right_of.as_deref().map(Placement::RightOf),
+            self.above.as_deref().map(Placement::Above),
+            self.below.as_deref().map(Placement::Below),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        match placements.as_slice() {
+            [] => Ok(Placement::AfterPrevious),
+            [placement] => Ok(*placement),
+            _ => Err(Error::Custom(format!(
+                "output '{}' can only be given one position",
+                self.name
+            ))),
+        }
+    }
+
+    /// The output this one is placed relative to, if any.
+    fn relative_to(&self) -> Option<&str> {
+        match self.placement().ok()? {
+            Placement::LeftOf(other)
+            | Placement::RightOf(other)
+            | Placement::Above(other)
+            | Placement::Below(other) => Some(other),
+            Placement::At(..) | Placement::AfterPrevious => None,
+        }
+    }
+
+    /// The width and height the output takes up on the screen in `mode`.
+    fn oriented_size(&self, mode: &ModeInfo) -> (u16, u16) {
+        if self.rotate.is_sideways() {
+            (mode.height, mode.width)
+        } else {
+            (mode.width, mode.height)
+        }
+    }
+
+    fn size(&self) -> Result<Option<(u16, u16)>> {
+        let Some(mode) = &self.mode else {
+            return Ok(None);
+        };
+
+        mode.split_once('x')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .map(Some)
+            .ok_or_else(|| {
+                Error::Custom(format!(
+                    "output '{}' has mode '{mode}': expected WIDTHxHEIGHT",
+                    self.name
+                ))
+            })
+    }
+}
+
+/// Check that outputs are only configured once, that at most one is primary
+/// and that each has a valid mode and at most one position, which doesn't
+/// lead back to itself.
+pub fn validate_outputs(outputs: &[OutputConfig]) -> Result<()> {
+    let names: BTreeSet<&str> = outputs.iter().map(|o| o.name.as_str()).collect();
+    if names.len() != outputs.len() {
+        return Err(Error::Custom(
+            "outputs can only be configured once".to_string(),
+        ));
+    }
+    if outputs.iter().filter(|o| o.primary).count() > 1 {
+        return Err(Error::Custom("only one output can be primary".to_string()));
+    }
+
+    for output in outputs {
+        output.placement()?;
+        output.size()?;
+    }
+
+    let by_name: BTreeMap<&str, &OutputConfig> =
+        outputs.iter().map(|o| (o.name.as_str(), o)).collect();
+    for output in outputs {
+        let mut chain = vec![output.name.as_str()];
+        let mut next = output.relative_to();
+        while let Some(name) = next {
+            if name == output.name {
+                return Err(Error::Custom(format!(
+                    "outputs are placed relative to each other in a loop: {chain:?}"
+                )));
+            }
+            // Loops that don't include this output are found from one that does
+            if chain.contains(&name) {
+                break;
+            }
+            chain.push(name);
+            next = by_name.get(name).and_then(|o| o.relative_to());
+        }
+    }
+
+    Ok(())
+}
+
+/// An output as we want it to be.
+#[derive(Debug)]
+struct Planned {
+    output: Output,
+    /// The CRTC currently driving the output, if any
+    crtc: Option<Crtc>,
+    mode: ModeInfo,
+    rotation: randr::Rotation,
+    x: i32,
+    y: i32,
+    w: u16,
+    h: u16,
+}
+
+/// Set up the connected outputs as described by `outputs`, leaving any that
+/// aren't mentioned as they are. Nothing is changed if everything is already
+/// as described, in which case this returns false.
+pub fn apply_outputs<C: Connection>(
+    conn: &C,
+    root: Window,
+    outputs: &[OutputConfig],
+) -> Result<bool> {
+    validate_outputs(outputs)?;
+    if outputs.is_empty() {
+        return Ok(false);
+    }
+
+    let res = conn.randr_get_screen_resources_current(root)?.reply()?;
+    let ts = res.config_timestamp;
+
+    let mut connected = BTreeMap::new();
+    for &output in res.outputs.iter() {
+        let info = conn.randr_get_output_info(output, ts)?.reply()?;
+        if info.connection == randr::Connection::CONNECTED {
+            let name = String::from_utf8_lossy(&info.name).into_owned();
+            connected.insert(name, (output, info));
+        }
+    }
+    let mut crtcs = BTreeMap::new();
+    for &crtc in res.crtcs.iter() {
+        crtcs.insert(crtc, conn.randr_get_crtc_info(crtc, ts)?.reply()?);
+    }
+
+    // Work out the mode and size of each configured output that is connected
+    let mut sizes = BTreeMap::new();
+    let mut to_disable = Vec::new();
+    for config in outputs.iter() {
+        let Some((output, info)) = connected.get(&config.name) else {
+            continue;
+        };
+        let crtc = Some(info.crtc).filter(|&c| c != 0);
+        if !config.enabled {
+            to_disable.extend(crtc);
+            continue;
+        }
+
+        let modes: Vec<&ModeInfo> = info
+            .modes
+            .iter()
+            .filter_map(|id| res.modes.iter().find(|m| m.id == *id))
+            .collect();
+        let mode = match config.size()? {
+            Some((w, h)) => modes.iter().find(|m| (m.width, m.height) == (w, h)),
+            // Preferred modes are listed first
+            None => modes.first(),
+        }
+        .ok_or_else(|| {
+            Error::Custom(format!(
+                "output '{}' has no {} mode",
+                config.name,
+                config.mode.as_deref().unwrap_or("usable")
+            ))
+        })?;
+        let (w, h) = config.oriented_size(mode);
+
+        sizes.insert(config.name.as_str(), (*output, crtc, **mode, w, h));
+    }
+
+    let positions = place(
+        outputs,
+        &sizes
+            .iter()
+            .map(|(&name, &(.., w, h))| (name, (w, h)))
+            .collect(),
+    );
+    let planned: Vec<Planned> = outputs
+        .iter()
+        .filter_map(|config| {
+            let &(output, crtc, mode, w, h) = sizes.get(config.name.as_str())?;
+            let &(x, y) = positions.get(config.name.as_str())?;
+            Some(Planned {
+                output,
+                crtc,
+                mode,
+                rotation: config.rotate.to_randr(),
+                x,
+                y,
+                w,
+                h,
+            })
+        })
+        .collect();
+
+    // Outputs that we don't manage keep their place and so count towards the
+    // size of the screen
+    let managed: BTreeSet<Output> = outputs
+        .iter()
+        .filter_map(|config| connected.get(&config.name).map(|(output, _)| *output))
+        .collect();
+    let unmanaged = crtcs
+        .values()
+        .filter(|info| info.mode != 0 && info.outputs.iter().all(|o| !managed.contains(o)));
+    let extents = planned
+        .iter()
+        .map(|p| (p.x + p.w as i32, p.y + p.h as i32))
+        .chain(unmanaged.map(|info| {
+            (
+                info.x as i32 + info.width as i32,
+                info.y as i32 + info.height as i32,
+            )
+        }));
+    let (screen_w, screen_h) = extents.fold((0, 0), |(w, h), (x, y)| (w.max(x), h.max(y)));
+
+    let changed: Vec<&Planned> = planned
+        .iter()
+        .filter(|p| {
+            let current = p.crtc.and_then(|crtc| crtcs.get(&crtc));
+            !current.is_some_and(|info| {
+                (info.x as i32, info.y as i32) == (p.x, p.y)
+                    && info.mode == p.mode.id
+                    && info.rotation == p.rotation
+                    && info.outputs == [p.output]
+            })
+        })
+        .collect();
+    let geometry = conn.get_geometry(root)?.reply()?;
+    let resize = (geometry.width as i32, geometry.height as i32) != (screen_w, screen_h);
+    let primary = outputs
+        .iter()
+        .find(|config| config.primary)
+        .and_then(|config| sizes.get(config.name.as_str()))
+        .map(|&(output, ..)| output);
+    let current_primary = conn.randr_get_output_primary(root)?.reply()?.output;
+    let set_primary = primary.filter(|&output| output != current_primary);
+
+    if to_disable.is_empty() && changed.is_empty() && !resize && set_primary.is_none() {
+        return Ok(false);
+    }
+
+    conn.grab_server()?;
+    let result = reconfigure(
+        conn,
+        root,
+        ts,
+        &to_disable,
+        &changed,
+        &crtcs,
+        (screen_w, screen_h),
+        set_primary,
+    );
+    conn.ungrab_server()?;
+    conn.flush()?;
+
+    result.map(|_| true)
+}
+
+/// The top left corner of the primary output, if there is one and it's on.
+pub fn primary_position<C: Connection>(conn: &C, root: Window) -> Result<Option<(i32, i32)>> {
+    let output = conn.randr_get_output_primary(root)?.reply()?.output;
+    if output == 0 {
+        return Ok(None);
+    }
+
+    let info = conn.randr_get_output_info(output, CURRENT_TIME)?.reply()?;
+    if info.crtc == 0 {
+        return Ok(None);
+    }
+    let crtc = conn.randr_get_crtc_info(info.crtc, CURRENT_TIME)?.reply()?;
+
+    Ok(Some((crtc.x as i32, crtc.y as i32)))
+}
+
+/// Positions for each output in `sizes`, which holds the width and height of
+/// those that are connected and on, shifted so that the top left of the
+/// screen is at (0, 0).
+fn place<'a>(
+    outputs: &'a [OutputConfig],
+    sizes: &BTreeMap<&'a str, (u16, u16)>,
+) -> BTreeMap<&'a str, (i32, i32)> {
+    let size = |name: &str| sizes.get(name).map(|&(w, h)| (w as i32, h as i32));
+    let right_edge = |positions: &BTreeMap<&str, (i32, i32)>| {
+        positions
+            .iter()
+            .filter_map(|(name, &(x, _))| Some(x + size(name)?.0))
+            .max()
+    };
+    let mut positions: BTreeMap<&str, (i32, i32)> = BTreeMap::new();
+
+    // Relative positions may refer to outputs later in the list, so keep
+    // going until nothing more can be placed
+    loop {
+        let mut progress = false;
+        for (ix, config) in outputs.iter().enumerate() {
+            let name = config.name.as_str();
+            let Some((w, h)) = size(name) else {
+                continue;
+            };
+            if positions.contains_key(name) {
+                continue;
+            }
+
+            let placed = |other: &str| Some((positions.get(other).copied()?, size(other)?));
+            let position = match config.placement().unwrap_or(Placement::AfterPrevious) {
+                Placement::At(x, y) => Some((x, y)),
+                Placement::LeftOf(other) => placed(other).map(|((x, y), _)| (x - w, y)),
+                Placement::RightOf(other) => placed(other).map(|((x, y), (ow, _))| (x + ow, y)),
+                Placement::Above(other) => placed(other).map(|((x, y), _)| (x, y - h)),
+                Placement::Below(other) => placed(other).map(|((x, y), (_, oh))| (x, y + oh)),
+                // Wait until everything listed before this has been placed
+                Placement::AfterPrevious => outputs[..ix]
+                    .iter()
+                    .all(|other| {
+                        let other = other.name.as_str();
+                        size(other).is_none() || positions.contains_key(other)
+                    })
+                    .then(|| (right_edge(&positions).unwrap_or(0), 0)),
+            };
+
+            if let Some(position) = position {
+                positions.insert(name, position);
+                progress = true;
+            }
+        }
+
+        if progress {
+            continue;
+        }
+
+        // Everything left is waiting on something that can't be placed yet,
+        // such as an output placed relative to one later in the list, so
+        // put the first of those without a position of its own on the right
+        let next = outputs.iter().find(|config| {
+            let name = config.name.as_str();
+            size(name).is_some()
+                && !positions.contains_key(name)
+                && config.placement().unwrap_or(Placement::AfterPrevious)
+                    == Placement::AfterPrevious
+        });
+        match next {
+            Some(config) => {
+                let x = right_edge(&positions).unwrap_or(0);
+                positions.insert(config.name.as_str(), (x, 0));
+            }
+            None => break,
+        }
+    }
+
+    // Anything left refers to an output that isn't connected (or to itself),
+    // so line those up to the right of everything else
+    for config in outputs.iter() {
+        let name = config.name.as_str();
+        if size(name).is_some() && !positions.contains_key(name) {
+            positions.insert(name, (right_edge(&positions).unwrap_or(0), 0));
+        }
+    }
+
+    let min_x = positions.values().map(|&(x, _)| x).min().unwrap_or(0);
+    let min_y = positions.values().map(|&(_, y)| y).min().unwrap_or(0);
+    for (x, y) in positions.values_mut() {
+        *x -= min_x;
+        *y -= min_y;
+    }
+
+    positions
+}
+
+#[allow(clippy::too_many_arguments)]
+fn reconfigure<C: Connection>(
+    conn: &C,
+    root: Window,
+    ts: u32,
+    to_disable: &[Crtc],
+    changed: &[&Planned],
+    crtcs: &BTreeMap<Crtc, randr::GetCrtcInfoReply>,
+    (screen_w, screen_h): (i32, i32),
+    primary: Option<Output>,
+) -> Result<()> {
+    let check = |status: SetConfig, what: &str| {
+        if status == SetConfig::SUCCESS {
+            Ok(())
+        } else {
+            Err(Error::Custom(format!("unable to {what}: {status:?}")))
+        }
+    };
+
+    // Turn off everything that is about to move so that the screen can be
+    // resized around the outputs that are staying put
+    let disable = to_disable
+        .iter()
+        .copied()
+        .chain(changed.iter().filter_map(|p| p.crtc));
+    for crtc in disable {
+        let reply = conn
+            .randr_set_crtc_config(
+                crtc,
+                CURRENT_TIME,
+                ts,
+                0,
+                0,
+                0,
+                randr::Rotation::ROTATE0,
+                &[],
+            )?
+            .reply()?;
+        check(reply.status, "disable an output")?;
+    }
+
+    let mm = |px: i32| (px as f64 * 25.4 / DPI).round() as u32;
+    conn.randr_set_screen_size(
+        root,
+        screen_w as u16,
+        screen_h as u16,
+        mm(screen_w),
+        mm(screen_h),
+    )?
+    .check()?;
+
+    let mut in_use: BTreeSet<Crtc> = crtcs
+        .iter()
+        .filter(|(crtc, info)| info.mode != 0 && !changed.iter().any(|p| p.crtc == Some(**crtc)))
+        .filter(|(crtc, _)| !to_disable.contains(crtc))
+        .map(|(&crtc, _)| crtc)
+        .collect();
+    for p in changed {
+        let info = conn.randr_get_output_info(p.output, ts)?.reply()?;
+        let crtc = p
+            .crtc
+            .filter(|crtc| !in_use.contains(crtc))
+            .or_else(|| {
+                info.crtcs
+                    .iter()
+                    .copied()
+                    .find(|crtc| !in_use.contains(crtc))
+            })
+            .ok_or_else(|| Error::Custom("no free CRTC for output".to_string()))?;
+        in_use.insert(crtc);
+
+        let reply = conn
+            .randr_set_crtc_config(
+                crtc,
+                CURRENT_TIME,
+                ts,
+                p.x as i16,
+                p.y as i16,
+                p.mode.id as Mode,
+                p.rotation,
+                &[p.output],
+            )?
+            .reply()?;
+        check(reply.status, "configure an output")?;
+    }
+
+    if let Some(output) = primary {
+        conn.randr_set_output_primary(root, output)?.check()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(json: &str) -> Vec<OutputConfig> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn mode(width: u16, height: u16) -> ModeInfo {
+        ModeInfo {
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    fn positions<'a>(
+        outputs: &'a [OutputConfig],
+        sizes: &[(&'a str, (u16, u16))],
+    ) -> Vec<(&'a str, (i32, i32))> {
+        place(outputs, &sizes.iter().copied().collect())
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn rotated_output_is_placed_by_its_rotated_size() {
+        let outputs = outputs(
+            r#"[
+                { "name": "DP-0", "rotate": "left", "right_of": "DP-2" },
+                { "name": "DP-2", "rotate": "right", "primary": true },
+                { "name": "HDMI-0", "below": "DP-0" }
+            ]"#,
+        );
+        let sizes: Vec<(&str, (u16, u16))> = outputs
+            .iter()
+            .map(|o| (o.name.as_str(), o.oriented_size(&mode(2560, 1440))))
+            .collect();
+
+        assert_eq!(sizes[0].1, (1440, 2560));
+        assert_eq!(
+            positions(&outputs, &sizes),
+            vec![
+                ("DP-0", (1440, 0)),
+                ("DP-2", (0, 0)),
+                ("HDMI-0", (1440, 2560))
+            ]
+        );
+    }
+
+    #[test]
+    fn outputs_left_of_and_above_are_shifted_onto_the_screen() {
+        let outputs = outputs(
+            r#"[
+                { "name": "eDP-1" },
+                { "name": "DP-1", "left_of": "eDP-1" },
+                { "name": "DP-2", "above": "DP-1" }
+            ]"#,
+        );
+        let sizes = [
+            ("eDP-1", (1920, 1080)),
+            ("DP-1", (2560, 1440)),
+            ("DP-2", (1920, 1080)),
+        ];
+
+        assert_eq!(
+            positions(&outputs, &sizes),
+            vec![
+                ("DP-1", (0, 1080)),
+                ("DP-2", (0, 0)),
+                ("eDP-1", (2560, 1080))
+            ]
+        );
+    }
+
+    #[test]
+    fn outputs_placed_by_a_missing_or_disabled_output_go_on_the_right() {
+        let outputs = outputs(
+            r#"[
+                { "name": "eDP-1", "position": [0, 0] },
+                { "name": "HDMI-1", "enabled": false },
+                { "name": "DP-1", "right_of": "HDMI-1" },
+                { "name": "DP-2", "below": "DP-3" },
+                { "name": "DP-4" }
+            ]"#,
+        );
+        // Only outputs that are connected and on have a size
+        let sizes = [
+            ("eDP-1", (1920, 1080)),
+            ("DP-1", (1280, 1024)),
+            ("DP-2", (1280, 1024)),
+            ("DP-4", (1000, 800)),
+        ];
+
+        assert_eq!(
+            positions(&outputs, &sizes),
+            vec![
+                ("DP-1", (2920, 0)),
+                ("DP-2", (4200, 0)),
+                ("DP-4", (1920, 0)),
+                ("eDP-1", (0, 0))
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_outputs() {
+        let valid = outputs(
+            r#"[
+                { "name": "DP-0", "right_of": "DP-2", "mode": "1920x1080" },
+                { "name": "DP-2", "primary": true, "below": "HDMI-0" },
+                { "name": "HDMI-0", "right_of": "eDP-1" }
+            ]"#,
+        );
+
+        assert!(validate_outputs(&valid).is_ok());
+    }
+
+    #[test]
+    fn invalid_outputs_are_rejected() {
+        for json in [
+            r#"[{ "name": "DP-0", "primary": true }, { "name": "DP-2", "primary": true }]"#,
+            r#"[{ "name": "DP-0" }, { "name": "DP-0" }]"#,
+            r#"[{ "name": "DP-0", "mode": "1920" }]"#,
+            r#"[{ "name": "DP-0", "position": [0, 0], "left_of": "DP-2" }]"#,
+        ] {
+            assert!(validate_outputs(&outputs(json)).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn placement_loops_are_rejected() {
+        for json in [
+            r#"[{ "name": "DP-0", "right_of": "DP-0" }]"#,
+            r#"[{ "name": "DP-0", "right_of": "DP-2" }, { "name": "DP-2", "below": "DP-0" }]"#,
+            r#"[
+                { "name": "eDP-1", "left_of": "DP-0" },
+                { "name": "DP-0", "right_of": "DP-2" },
+                { "name": "DP-2", "above": "HDMI-0" },
+                { "name": "HDMI-0", "left_of": "DP-0" }
+            ]"#,
+        ] {
+            let err = validate_outputs(&outputs(json)).unwrap_err().to_string();
+            assert!(err.contains("loop"), "{err}");
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use penrose::{Error, Result};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+/// Whether a process whose executable, or first argument, is named exactly
+/// `program` is running, going by `/proc`.
+pub fn is_running(program: &str) -> Result<bool> {
+    is_running_in(Path::new("/proc"), program)
+}
+
+/// [is_running] going by the process directories in `proc_root`.
+pub fn is_running_in(proc_root: &Path, program: &str) -> Result<bool> {
+    for entry in fs::read_dir(proc_root)? {
+        let entry = entry?;
+        let is_pid = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit()));
+
+        // Processes can exit while we're looking, so anything unreadable is
+        // skipped rather than treated as an error
+        if is_pid
+            && process_names(&entry.path())
+                .iter()
+                .any(|name| name == program)
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// The most recently started descendant of `pid`, found by following the
+/// newest child down from it: for a terminal this is the shell inside, or
+/// whatever that shell is running. `proc_root` is `/proc` outside of tests.
+pub fn newest_descendant(proc_root: &Path, pid: u32) -> u32 {
+    let mut children: HashMap<u32, Vec<(u64, u32)>> = HashMap::new();
+    for entry in fs::read_dir(proc_root).into_iter().flatten().flatten() {
+        let Some(child) = entry.file_name().to_str().and_then(|n| n.parse().ok()) else {
+            continue;
+        };
+        if let Some((parent, started)) = parent_and_start_time(&entry.path()) {
+            children.entry(parent).or_default().push((started, child));
+        }
+    }
+
+    // Bounded in case a pid has been reused in the middle of a chain
+    let mut pid = pid;
+    for _ in 0..children.len() {
+        match children.get(&pid).and_then(|c| c.iter().max()) {
+            Some(&(_, child)) => pid = child,
+            None => break,
+        }
+    }
+
+    pid
+}
+
+/// The working directory of the newest descendant of `pid`, or of `pid`
+/// itself if that can't be read.
+pub fn descendant_cwd(proc_root: &Path, pid: u32) -> Option<PathBuf> {
+    let cwd = |pid: u32| fs::read_link(proc_root.join(pid.to_string()).join("cwd")).ok();
+
+    cwd(newest_descendant(proc_root, pid)).or_else(|| cwd(pid))
+}
+
+/// The parent pid and start time, in clock ticks since boot, from the `stat`
+/// of the process at `proc_dir`.
+fn parent_and_start_time(proc_dir: &Path) -> Option<(u32, u64)> {
+    let stat = fs::read_to_string(proc_dir.join("stat")).ok()?;
+    // The command name in brackets can contain anything, including spaces
+    // and brackets, so fields are counted from the last ')'
+    let (_, fields) = stat.rsplit_once(')')?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+
+    Some((fields.get(1)?.parse().ok()?, fields.get(19)?.parse().ok()?))
+}
+
+/// The executable name and `argv[0]` name of the process at `proc_dir`.
+fn process_names(proc_dir: &Path) -> Vec<String> {
+    let exe = fs::read_link(proc_dir.join("exe"))
+        .ok()
+        .and_then(|path| file_name(&path));
+    let argv0 = fs::read(proc_dir.join("cmdline")).ok().and_then(|cmdline| {
+        let arg = cmdline.split(|&b| b == 0).next()?;
+        file_name(Path::new(&*String::from_utf8_lossy(arg)))
+    });
+
+    exe.into_iter().chain(argv0).collect()
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// The path `program` would be run from: either `program` itself if it
+/// contains a `/`, or the first executable file of that name in `$PATH`.
+pub fn find_in_path(program: &str) -> Result<Option<PathBuf>> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return Ok(is_executable(&path).then_some(path));
+    }
+
+    let paths = env::var_os("PATH").ok_or_else(|| Error::Custom("PATH is not set".to_string()))?;
+
+    Ok(find_in_dirs(program, &paths))
+}
+
+/// The first executable file named `program` in the `:` separated list of
+/// directories `path`, as `$PATH` is searched.
+pub fn find_in_dirs(program: &str, path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+pub fn is_in_path(program: &str) -> Result<bool> {
+    find_in_path(program).map(|path| path.is_some())
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::{ffi::OsString, os::unix::fs::symlink};
+
+    /// Add `<pid>/stat` for a process to a fake `/proc`.
+    fn process(proc_root: &TempDir, pid: u32, comm: &str, ppid: u32, started: u64) {
+        let unused = vec!["0"; 17].join(" ");
+        proc_root.write(
+            format!("{pid}/stat"),
+            &format!("{pid} ({comm}) S {ppid} {unused} {started} 0 0\n"),
+        );
+    }
+
+    /// A terminal (100) running two shells, the newer of which runs vim.
+    fn terminal(proc_root: &TempDir) {
+        process(proc_root, 1, "systemd", 0, 1);
+        process(proc_root, 100, "alacritty", 1, 1000);
+        process(proc_root, 200, "nu", 100, 1100);
+        process(proc_root, 201, "zsh", 100, 1200);
+        process(proc_root, 300, "vim (1) )", 201, 1300);
+        // Read from the first ')' this would look like a child of 100
+        process(proc_root, 202, "sh) S 100", 1, 9000);
+    }
+
+    /// Give a process in a fake `/proc` an executable and command line.
+    fn program(proc_root: &TempDir, pid: u32, exe: &str, cmdline: &[&str]) {
+        let cmdline: String = cmdline.iter().map(|arg| format!("{arg}\0")).collect();
+        proc_root.write(format!("{pid}/cmdline"), &cmdline);
+        symlink(exe, proc_root.path().join(format!("{pid}/exe"))).unwrap();
+    }
+
+    #[test]
+    fn running_programs_are_matched_by_exact_name() {
+        let proc_root = TempDir::new();
+        program(&proc_root, 100, "/usr/bin/alacritty", &["alacritty"]);
+        program(
+            &proc_root,
+            200,
+            "/usr/bin/python3",
+            &["/home/user/bin/sync-notes", "--watch"],
+        );
+        // Not a process, so its program is never looked at
+        program(&proc_root, 0, "/usr/bin/nitrogen", &[]);
+        fs::rename(proc_root.path().join("0"), proc_root.path().join("self")).unwrap();
+        let running = |name| is_running_in(proc_root.path(), name).unwrap();
+
+        assert!(running("alacritty"));
+        assert!(running("python3"));
+        assert!(running("sync-notes"));
+        assert!(!running("alacrit"));
+        assert!(!running("alacritty-wrapped"));
+        assert!(!running("python"));
+        assert!(!running("--watch"));
+        assert!(!running("nitrogen"));
+    }
+
+    #[test]
+    fn newest_child_is_followed_down() {
+        let proc_root = TempDir::new();
+        terminal(&proc_root);
+
+        assert_eq!(newest_descendant(proc_root.path(), 100), 300);
+        assert_eq!(newest_descendant(proc_root.path(), 200), 200);
+        assert_eq!(newest_descendant(proc_root.path(), 999), 999);
+    }
+
+    #[test]
+    fn command_names_with_brackets_and_spaces_are_skipped_over() {
+        let proc_root = TempDir::new();
+        process(&proc_root, 10, "a) 1 2 (b", 1, 50);
+
+        assert_eq!(
+            parent_and_start_time(&proc_root.path().join("10")),
+            Some((1, 50))
+        );
+    }
+
+    #[test]
+    fn reused_pids_forming_a_loop_end() {
+        let proc_root = TempDir::new();
+        process(&proc_root, 400, "a", 401, 10);
+        process(&proc_root, 401, "b", 400, 20);
+
+        let found = newest_descendant(proc_root.path(), 400);
+
+        assert!(found == 400 || found == 401);
+    }
+
+    #[test]
+    fn cwd_of_the_newest_descendant() {
+        let proc_root = TempDir::new();
+        terminal(&proc_root);
+        symlink("/home/user/project", proc_root.path().join("300/cwd")).unwrap();
+        symlink("/home/user", proc_root.path().join("100/cwd")).unwrap();
+
+        assert_eq!(
+            descendant_cwd(proc_root.path(), 100),
+            Some(PathBuf::from("/home/user/project"))
+        );
+    }
+
+    #[test]
+    fn cwd_falls_back_to_the_process_itself() {
+        let proc_root = TempDir::new();
+        terminal(&proc_root);
+        symlink("/home/user", proc_root.path().join("100/cwd")).unwrap();
+
+        assert_eq!(
+            descendant_cwd(proc_root.path(), 100),
+            Some(PathBuf::from("/home/user"))
+        );
+        assert_eq!(descendant_cwd(proc_root.path(), 200), None);
+    }
+
+    fn executable(dir: &TempDir, path: &str) -> PathBuf {
+        let path = dir.write(path, "#!/bin/sh\n");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        path
+    }
+
+    fn search_path(dir: &TempDir, dirs: &[&str]) -> OsString {
+        env::join_paths(dirs.iter().map(|d| dir.path().join(d))).unwrap()
+    }
+
+    #[test]
+    fn first_executable_in_path_order_is_found() {
+        let dir = TempDir::new();
+        executable(&dir, "a/prog");
+        let expected = executable(&dir, "b/prog");
+        executable(&dir, "c/prog");
+
+        let path = search_path(&dir, &["missing", "b", "a", "c"]);
+
+        assert_eq!(find_in_dirs("prog", &path), Some(expected));
+    }
+
+    #[test]
+    fn files_that_are_not_executable_are_skipped() {
+        let dir = TempDir::new();
+        dir.write("a/prog", "not a program");
+        let expected = executable(&dir, "b/prog");
+
+        let path = search_path(&dir, &["a", "b"]);
+
+        assert_eq!(find_in_dirs("prog", &path), Some(expected));
+    }
+
+    #[test]
+    fn directories_are_skipped() {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.path().join("a/prog")).unwrap();
+        fs::set_permissions(dir.path().join("a/prog"), fs::Permissions::from_mode(0o755)).unwrap();
+        let expected = executable(&dir, "b/prog");
+
+        let path = search_path(&dir, &["a", "b"]);
+
+        assert_eq!(find_in_dirs("prog", &path), Some(expected));
+    }
+
+    #[test]
+    fn nothing_is_found_without_an_executable() {
+        let dir = TempDir::new();
+        dir.write("a/prog", "not a program");
+        fs::create_dir_all(dir.path().join("b/prog")).unwrap();
+
+        let path = search_path(&dir, &["a", "b"]);
+
+        assert_eq!(find_in_dirs("prog", &path), None);
+        assert_eq!(find_in_dirs("prog", OsStr::new("")), None);
+    }
+
+    #[test]
+    fn programs_with_a_slash_are_not_searched_for() {
+        let dir = TempDir::new();
+        let program = executable(&dir, "bin/prog");
+        let not_executable = dir.write("bin/data", "");
+
+        assert_eq!(
+            find_in_path(program.to_str().unwrap()).unwrap(),
+            Some(program.clone())
+        );
+        assert_eq!(
+            find_in_path(not_executable.to_str().unwrap()).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use penrose::{
+    core::{Config, State},
+    x::{XConn, XEvent},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+use sysinfo::{Pid, Process, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+use crate::{workspaces::client_pids, SYSTEM};
+
+/// How often processes that have exited are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a process is trusted for before it's read again on lookup, in
+/// case its pid has been reused or it has exec'd something else.
+const TTL: Duration = Duration::from_secs(10);
+
+/// A process table shared between threads that stays up to date: processes
+/// are read when first looked up or once they've been cached for longer than
+/// [TTL], and a background sweep drops those that have exited.
+#[derive(Debug)]
+pub struct ProcessCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    system: System,
+    refreshed: HashMap<Pid, Instant>,
+}
+
+impl Default for ProcessCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessCache {
+    /// An empty cache, swept from a background thread until it's dropped.
+    pub fn new() -> Self {
+        // Otherwise sysinfo holds on to a file per process between refreshes
+        sysinfo::set_open_files_limit(0);
+
+        let cache = Self {
+            inner: Arc::new(Mutex::new(Inner {
+                system: System::new(),
+                refreshed: HashMap::new(),
+            })),
+        };
+
+        let inner = Arc::downgrade(&cache.inner);
+        thread::spawn(move || sweep_until_dropped(inner));
+
+        cache
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Read these processes again now, such as when a new window appears.
+    pub fn refresh(&self, pids: &[u32]) {
+        let pids: Vec<Pid> = pids.iter().map(|&pid| Pid::from_u32(pid)).collect();
+        self.lock().refresh(&pids);
+    }
+
+    /// Run `f` on the process with this pid, if it's running.
+    pub fn with_process<T>(&self, pid: u32, f: impl FnOnce(&Process) -> T) -> Option<T> {
+        let pid = Pid::from_u32(pid);
+        let mut inner = self.lock();
+        let fresh = inner
+            .refreshed
+            .get(&pid)
+            .is_some_and(|at| at.elapsed() < TTL);
+        if !fresh {
+            inner.refresh(&[pid]);
+        }
+
+        inner.system.process(pid).map(f)
+    }
+
+    /// The parent of `pid`, its parent and so on up to init.
+    pub fn ancestors(&self, pid: u32) -> Vec<u32> {
+        let mut ancestors = Vec::new();
+        let mut pid = pid;
+        while let Some(parent) = self.with_process(pid, |p| p.parent().map(Pid::as_u32)) {
+            match parent {
+                Some(parent) if parent != 0 && !ancestors.contains(&parent) => {
+                    ancestors.push(parent);
+                    pid = parent;
+                }
+                _ => break,
+            }
+        }
+
+        ancestors
+    }
+}
+
+impl Inner {
+    fn refresh(&mut self, pids: &[Pid]) {
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(pids),
+            true,
+            refresh_kind(),
+        );
+        let now = Instant::now();
+        for pid in pids {
+            self.refreshed.insert(*pid, now);
+        }
+    }
+
+    /// Drop the processes that aren't among `running`. Those that started
+    /// since `running` was listed are checked again rather than dropped.
+    fn drop_exited(&mut self, running: &HashSet<Pid>) {
+        let gone: Vec<Pid> = self
+            .system
+            .processes()
+            .keys()
+            .filter(|pid| !running.contains(pid))
+            .copied()
+            .collect();
+        self.system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&gone),
+            true,
+            ProcessRefreshKind::nothing(),
+        );
+        let system = &self.system;
+        self.refreshed
+            .retain(|pid, _| system.process(*pid).is_some());
+    }
+}
+
+/// Everything is read again once the [TTL] is up, in case the pid now
+/// belongs to another program or the process has exec'd one.
+fn refresh_kind() -> ProcessRefreshKind {
+    ProcessRefreshKind::nothing()
+        .with_exe(UpdateKind::Always)
+        .with_cmd(UpdateKind::Always)
+        .with_cwd(UpdateKind::Always)
+}
+
+/// The pids in `/proc`.
+fn running_pids() -> HashSet<Pid> {
+    fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .map(Pid::from_u32)
+        .collect()
+}
+
+fn sweep_until_dropped(inner: Weak<Mutex<Inner>>) {
+    loop {
+        thread::sleep(SWEEP_INTERVAL);
+        // Listed before taking the lock, which the bar and hooks wait on
+        let running = running_pids();
+        let Some(inner) = inner.upgrade() else {
+            break;
+        };
+        let mut inner = inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.drop_exited(&running);
+    }
+}
+
+/// Keep the shared [SYSTEM] cache up to date with the processes behind new
+/// windows as they are mapped.
+pub fn add_process_cache_hooks<X>(mut config: Config<X>) -> Config<X>
+where
+    X: XConn + 'static,
+{
+    config.compose_or_set_event_hook(|event: &XEvent, _: &mut State<X>, x: &X| {
+        if let XEvent::MapRequest(client) = event {
+            if let Some(pids) = client_pids(x, *client) {
+                SYSTEM.refresh(&pids);
+            }
+        }
+
+        Ok(true)
+    });
+
+    config
+}
@@ -0,0 +1,90 @@
+use penrose::{
+    core::{hooks::EventHook, State},
+    x::{
+        event::{ClientEventMask, ClientMessage},
+        XConn, XEvent,
+    },
+    x11rb::RustConn,
+    Error, Result,
+};
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Arc,
+};
+
+use crate::log::LogPenroseError;
+
+/// The client message type used to wake the window manager when there are
+/// queued commands waiting to run.
+pub const REMOTE_WAKE_ATOM: &str = "_PENROSE_BBARKER_REMOTE_WAKE";
+
+/// Something to run against the window manager state from another thread.
+pub type RemoteCommand<X> = Box<dyn FnOnce(&mut State<X>, &X) -> Result<()> + Send>;
+
+/// The sending half of a remote command queue: this can be cloned and handed
+/// to other threads (signal handlers, sockets...) that need to act on the
+/// running window manager.
+pub struct RemoteSender<X: XConn> {
+    tx: Sender<RemoteCommand<X>>,
+    conn: Arc<RustConn>,
+}
+
+impl<X: XConn> Clone for RemoteSender<X> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            conn: self.conn.clone(),
+        }
+    }
+}
+
+impl<X: XConn> RemoteSender<X> {
+    /// Queue `command` and wake the window manager so that it is run promptly.
+    pub fn send(&self, command: RemoteCommand<X>) -> Result<()> {
+        self.tx
+            .send(command)
+            .map_err(|_| Error::Custom("window manager is no longer running".to_string()))?;
+
+        // The main loop blocks waiting on X events so we poke it with a
+        // client message on the root window.
+        let msg = ClientMessage::new(
+            self.conn.root(),
+            ClientEventMask::SubstructureNotify,
+            REMOTE_WAKE_ATOM,
+            [0u32; 5].into(),
+        );
+        self.conn.send_client_message(msg)?;
+        self.conn.flush();
+
+        Ok(())
+    }
+}
+
+/// The receiving half of a remote command queue, see [RemoteReceiver::event_hook].
+pub struct RemoteReceiver<X: XConn> {
+    rx: Receiver<RemoteCommand<X>>,
+}
+
+impl<X: XConn> RemoteReceiver<X> {
+    /// An [EventHook] that runs any queued commands when woken by a [RemoteSender].
+    pub fn event_hook(self) -> impl EventHook<X> {
+        move |event: &XEvent, state: &mut State<X>, x: &X| match event {
+            XEvent::ClientMessage(msg) if msg.dtype == REMOTE_WAKE_ATOM => {
+                for command in self.rx.try_iter() {
+                    command(state, x).log_err("Error running remote command");
+                }
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+}
+
+/// Create a new remote command queue. This opens its own connection to the X
+/// server for waking up the window manager.
+pub fn remote_channel<X: XConn>() -> Result<(RemoteSender<X>, RemoteReceiver<X>)> {
+    let (tx, rx) = channel();
+    let conn = Arc::new(RustConn::new()?);
+
+    Ok((RemoteSender { tx, conn }, RemoteReceiver { rx }))
+}
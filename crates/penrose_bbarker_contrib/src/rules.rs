@@ -0,0 +1,377 @@
+use penrose::{
+    core::State,
+    pure::geometry::{Rect, RelativeRect},
+    x::{Query, XConn, XConnExt},
+    Error, Result, Xid,
+};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+
+use crate::{
+    workspaces::{client_classes, client_pids, process_name},
+    SYSTEM,
+};
+
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TitleRegex(Regex);
+
+impl TitleRegex {
+    pub fn is_match(&self, title: &str) -> bool {
+        self.0.is_match(title)
+    }
+}
+
+impl TryFrom<String> for TitleRegex {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        Regex::new(&s)
+            .map(TitleRegex)
+            .map_err(|e| Error::Custom(format!("invalid title regex '{s}': {e}")))
+    }
+}
+
+impl fmt::Debug for TitleRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0.as_str())
+    }
+}
+
+impl PartialEq for TitleRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+/// What a [WindowRule] matches a new client against.
+///
+/// In a config file this is written as one of `class`, `title_regex` or `exe`
+/// alongside the rule's actions.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMatch {
+    /// Either of the `WM_CLASS` strings, exactly
+    Class(String),
+    TitleRegex(TitleRegex),
+    /// The executable name of the process owning the window, exactly
+    Exe(String),
+}
+
+/// Where to put clients matching a [WindowMatch] when they are first managed.
+///
+/// ```toml
+/// [[rules]]
+/// class = "firefox"
+/// tag = "2"
+///
+/// [[rules]]
+/// exe = "pavucontrol"
+/// geometry = { x = 0.6, y = 0.05, w = 0.35, h = 0.4 }
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowRule {
+    #[serde(flatten)]
+    pub matcher: WindowMatch,
+    /// Move the client to this workspace
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// Move the client to whichever workspace is on this screen, unless `tag`
+    /// is also set. Ignored if there is no such screen.
+    #[serde(default)]
+    pub screen: Option<usize>,
+    /// Float the client centered on its screen at the size it asked for
+    #[serde(default)]
+    pub floating: bool,
+    /// Float the client at this position, as fractions of its screen
+    #[serde(default, deserialize_with = "relative_rect")]
+    pub geometry: Option<RelativeRect>,
+}
+
+/// Values outside of 0.0 to 1.0 are clamped, as they are by [RelativeRect::new].
+fn relative_rect<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<RelativeRect>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Fractions {
+        x: f64,
+        y: f64,
+        w: f64,
+        h: f64,
+    }
+
+    let Fractions { x, y, w, h } = Fractions::deserialize(deserializer)?;
+
+    Ok(Some(RelativeRect::new(x, y, w, h)))
+}
+
+/// The parts of a client that window rules can match on.
+#[derive(Debug, Clone, Default)]
+pub struct ClientMatchInfo {
+    pub title: String,
+    pub exe: Option<String>,
+    pub classes: Vec<String>,
+}
+
+impl ClientMatchInfo {
+    /// The exe is only looked up when `with_exe` is set, as that means
+    /// reading the owning process.
+    pub fn new<X: XConn>(x: &X, client: Xid, with_exe: bool) -> Self {
+        Self {
+            title: x.window_title(client).unwrap_or_default(),
+            exe: if with_exe {
+                client_exe(x, client)
+            } else {
+                None
+            },
+            classes: client_classes(x, client),
+        }
+    }
+}
+
+/// The executable name of the process that owns a client.
+pub fn client_exe<X: XConn>(x: &X, client: Xid) -> Option<String> {
+    client_pids(x, client)?
+        .into_iter()
+        .find_map(|pid| process_name(&SYSTEM, pid))
+}
+
+impl WindowMatch {
+    pub fn matches(&self, client: &ClientMatchInfo) -> bool {
+        match self {
+            WindowMatch::Class(class) => client.classes.contains(class),
+            WindowMatch::TitleRegex(re) => re.is_match(&client.title),
+            WindowMatch::Exe(exe) => client.exe.as_ref() == Some(exe),
+        }
+    }
+}
+
+impl<X: XConn> Query<X> for WindowMatch {
+    fn run(&self, id: Xid, x: &X) -> Result<bool> {
+        let with_exe = matches!(self, WindowMatch::Exe(_));
+
+        Ok(self.matches(&ClientMatchInfo::new(x, id, with_exe)))
+    }
+}
+
+impl WindowRule {
+    pub fn matches(&self, client: &ClientMatchInfo) -> bool {
+        self.matcher.matches(client)
+    }
+}
+
+/// Apply every rule matching `client`, in order, with the first rule to set
+/// a tag or floating position winning over later ones. Intended to be run
+/// from a manage hook.
+pub fn apply_window_rules<X: XConn>(
+    rules: &[WindowRule],
+    client: Xid,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let with_exe = rules
+        .iter()
+        .any(|rule| matches!(rule.matcher, WindowMatch::Exe(_)));
+    let info = ClientMatchInfo::new(x, client, with_exe);
+
+    let mut tag = None;
+    let mut floating = None;
+    for rule in rules.iter().filter(|rule| rule.matches(&info)) {
+        let cs = &state.client_set;
+        let rule_tag = rule.tag.clone().or_else(|| {
+            rule.screen
+                .and_then(|s| cs.tag_for_screen(s).map(String::from))
+        });
+        tag = tag.or(rule_tag);
+
+        floating = floating.or(match rule.geometry {
+            Some(r) => Some(Some(r)),
+            None if rule.floating => Some(None),
+            None => None,
+        });
+    }
+
+    // Floating positions are relative to the screen, so this needs doing
+    // while the client is still visible on the screen it was managed on
+    if let Some(geometry) = floating {
+        let r_screen = state
+            .client_set
+            .screen_for_client(&client)
+            .ok_or(Error::ClientIsNotVisible(client))?
+            .geometry();
+        let r = match geometry {
+            Some(relative) => relative.applied_to(&r_screen),
+            None => centered(x.client_geometry(client)?, r_screen),
+        };
+        state.client_set.float(client, r)?;
+    }
+
+    if let Some(tag) = tag {
+        if !state.client_set.contains_tag(&tag) {
+            return Err(Error::Custom(format!(
+                "window rule has unknown tag '{tag}'"
+            )));
+        }
+        state.client_set.move_client_to_tag(&client, &tag);
+    }
+
+    Ok(())
+}
+
+/// Center `r` in `r_screen`, shrinking it to fit if needed.
+fn centered(r: Rect, r_screen: Rect) -> Rect {
+    let r = Rect {
+        w: r.w.min(r_screen.w),
+        h: r.h.min(r_screen.h),
+        ..r
+    };
+
+    r.centered_in(&r_screen).unwrap_or(r_screen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeConn;
+    use std::{env, process};
+
+    const FIREFOX: u32 = 1;
+
+    /// Rules as they'd be written in TOML, but in JSON as that's what we
+    /// depend on here.
+    fn rules(json: &str) -> Vec<WindowRule> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn conn() -> FakeConn {
+        FakeConn::new(vec![
+            Rect::new(0, 0, 1000, 800),
+            Rect::new(1000, 0, 1000, 800),
+        ])
+        .with_client(FIREFOX, &["Navigator", "firefox"], "Mozilla Firefox", 0)
+    }
+
+    fn managed(x: &FakeConn) -> State<FakeConn> {
+        let mut state = x.state(&["1", "2", "3", "4"]);
+        state.client_set.insert(Xid::from(FIREFOX));
+
+        state
+    }
+
+    #[test]
+    fn first_matching_tag_wins() {
+        let x = conn();
+        let mut state = managed(&x);
+        let rules = rules(
+            r#"[
+                {"title_regex": "^Thunderbird$", "tag": "4"},
+                {"class": "firefox", "tag": "3"},
+                {"class": "Navigator", "tag": "4"}
+            ]"#,
+        );
+
+        apply_window_rules(&rules, Xid::from(FIREFOX), &mut state, &x).unwrap();
+
+        assert_eq!(
+            state.client_set.tag_for_client(&Xid::from(FIREFOX)),
+            Some("3")
+        );
+    }
+
+    #[test]
+    fn screen_moves_to_the_workspace_shown_there() {
+        let x = conn();
+        let mut state = managed(&x);
+        let rules = rules(r#"[{"class": "firefox", "screen": 1}]"#);
+
+        apply_window_rules(&rules, Xid::from(FIREFOX), &mut state, &x).unwrap();
+
+        let on_screen_1 = state.client_set.tag_for_screen(1).map(String::from);
+        assert_eq!(
+            state.client_set.tag_for_client(&Xid::from(FIREFOX)),
+            on_screen_1.as_deref()
+        );
+    }
+
+    #[test]
+    fn unknown_screen_is_ignored() {
+        let x = conn();
+        let mut state = managed(&x);
+        let rules = rules(r#"[{"class": "firefox", "screen": 5}]"#);
+
+        apply_window_rules(&rules, Xid::from(FIREFOX), &mut state, &x).unwrap();
+
+        assert_eq!(
+            state.client_set.tag_for_client(&Xid::from(FIREFOX)),
+            Some("1")
+        );
+    }
+
+    #[test]
+    fn unknown_tag_is_an_error() {
+        let x = conn();
+        let mut state = managed(&x);
+        let rules = rules(r#"[{"class": "firefox", "tag": "web"}]"#);
+
+        assert!(apply_window_rules(&rules, Xid::from(FIREFOX), &mut state, &x).is_err());
+    }
+
+    #[test]
+    fn geometry_is_relative_to_the_screen() {
+        let x = conn();
+        let mut state = managed(&x);
+        let rules = rules(
+            r#"[
+                {"title_regex": "Firefox$", "geometry": {"x": 0.5, "y": 0.5, "w": 0.5, "h": 0.25}},
+                {"class": "firefox", "floating": true}
+            ]"#,
+        );
+
+        apply_window_rules(&rules, Xid::from(FIREFOX), &mut state, &x).unwrap();
+
+        assert_eq!(
+            state.client_set.sink(&Xid::from(FIREFOX)),
+            Some(Rect::new(500, 400, 500, 200))
+        );
+    }
+
+    #[test]
+    fn floating_centers_on_the_screen() {
+        let x = conn();
+        let mut state = managed(&x);
+        let rules = rules(r#"[{"class": "firefox", "floating": true}]"#);
+
+        apply_window_rules(&rules, Xid::from(FIREFOX), &mut state, &x).unwrap();
+
+        assert_eq!(
+            state.client_set.sink(&Xid::from(FIREFOX)),
+            Some(Rect::new(300, 250, 400, 300))
+        );
+    }
+
+    #[test]
+    fn exe_is_read_from_the_owning_process() {
+        let exe = env::current_exe().unwrap();
+        let exe = exe.file_name().unwrap().to_string_lossy();
+        let x = conn().with_client(2, &["test"], "test", process::id());
+        let mut state = managed(&x);
+        state.client_set.insert(Xid::from(2));
+        let rules = rules(&format!(r#"[{{"exe": "{exe}", "tag": "2"}}]"#));
+
+        apply_window_rules(&rules, Xid::from(2), &mut state, &x).unwrap();
+        apply_window_rules(&rules, Xid::from(FIREFOX), &mut state, &x).unwrap();
+
+        assert_eq!(state.client_set.tag_for_client(&Xid::from(2)), Some("2"));
+        assert_eq!(
+            state.client_set.tag_for_client(&Xid::from(FIREFOX)),
+            Some("1")
+        );
+    }
+}
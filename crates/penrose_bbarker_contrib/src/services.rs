@@ -0,0 +1,421 @@
+use penrose::{
+    x::{property::Prop, XConn},
+    Error, Result,
+};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, io,
+    path::Path,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use crate::{is_running, log::log_penrose, process::find_in_path, HOSTNAME};
+
+/// How often the supervisor checks on its services.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// A service that exits sooner than this after starting counts as crashing.
+const MIN_UPTIME: Duration = Duration::from_secs(10);
+/// Crashes in a row before a service is no longer restarted.
+const MAX_CRASHES: u32 = 5;
+/// Set on the root window once services have been started, so that it is
+/// gone along with the X server at the end of the session.
+const STARTED_PROP: &str = "_DOTPENROSE_SERVICES_STARTED";
+
+/// A program started alongside the window manager.
+///
+/// ```toml
+/// [[services]]
+/// name = "picom"
+/// command = "picom"
+/// only_if_missing = true
+/// restart_on_exit = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Service {
+    pub name: String,
+    /// `~` and `$VARS` are expanded in the command and its args
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Don't start it if a process with the same name is already running
+    #[serde(default)]
+    pub only_if_missing: bool,
+    /// Services that must have started (or finished successfully) first
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Start it again whenever it exits, unless it keeps crashing
+    #[serde(default)]
+    pub restart_on_exit: bool,
+    /// Only start it with the X session, not again when the window manager
+    /// is restarted
+    #[serde(default)]
+    pub once_per_session: bool,
+    /// Only start it on these hosts, or on every host if empty
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+impl Service {
+    pub fn enabled_on(&self, host: &str) -> bool {
+        self.hosts.is_empty() || self.hosts.iter().any(|h| h == host)
+    }
+
+    fn program_name(&self) -> String {
+        let command = expand_vars(&self.command);
+        Path::new(&command)
+            .file_name()
+            .map_or(command.clone(), |name| name.to_string_lossy().into_owned())
+    }
+
+    fn spawn(&self) -> io::Result<Child> {
+        Command::new(expand_vars(&self.command))
+            .args(self.args.iter().map(|arg| expand_vars(arg)))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    }
+}
+
+/// Expand a leading `~` and any `$NAME` or `${NAME}` environment variables.
+/// Unset variables expand to nothing.
+pub fn expand_vars(s: &str) -> String {
+    let s = match (s.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => home + rest,
+        _ => s.to_string(),
+    };
+
+    let mut expanded = String::new();
+    let mut rest = s.as_str();
+    while let Some(ix) = rest.find('$') {
+        expanded.push_str(&rest[..ix]);
+        rest = &rest[ix + 1..];
+
+        let (name, len) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            expanded.push_str(&env::var(name).unwrap_or_default());
+        }
+        rest = &rest[len..];
+    }
+    expanded.push_str(rest);
+
+    expanded
+}
+
+/// Check that service names are unique and that dependencies exist and don't
+/// form a cycle.
+pub fn validate_services(services: &[Service]) -> Result<()> {
+    let invalid = |msg: String| Err(Error::Custom(msg));
+    let by_name: BTreeMap<&str, &Service> = services.iter().map(|s| (s.name.as_str(), s)).collect();
+
+    if by_name.len() != services.len() {
+        return invalid("duplicate service names".to_string());
+    }
+    for service in services {
+        if let Some(dep) = service
+            .depends_on
+            .iter()
+            .find(|dep| !by_name.contains_key(dep.as_str()))
+        {
+            return invalid(format!(
+                "service '{}' depends on unknown service '{dep}'",
+                service.name
+            ));
+        }
+    }
+
+    // Repeatedly drop services whose dependencies have all been dropped:
+    // whatever is left over depends on itself somehow
+    let mut remaining: Vec<&Service> = services.iter().collect();
+    loop {
+        let before = remaining.len();
+        let names: Vec<&str> = remaining.iter().map(|s| s.name.as_str()).collect();
+        remaining.retain(|s| s.depends_on.iter().any(|dep| names.contains(&dep.as_str())));
+
+        if remaining.is_empty() {
+            return Ok(());
+        } else if remaining.len() == before {
+            let names: Vec<&str> = remaining.iter().map(|s| s.name.as_str()).collect();
+            return invalid(format!("services depend on each other: {names:?}"));
+        }
+    }
+}
+
+enum Status {
+    Pending,
+    Running {
+        child: Child,
+        since: Instant,
+        crashes: u32,
+    },
+    /// Finished successfully or was already running
+    Done,
+    Failed,
+}
+
+impl Status {
+    /// Whether services depending on this one can be started
+    fn is_up(&self) -> bool {
+        matches!(self, Status::Running { .. } | Status::Done)
+    }
+}
+
+/// Whether services have already been started in this X session, by an
+/// earlier run of the window manager. Marks them as started for next time.
+pub fn restarted_in_session<X: XConn>(x: &X) -> Result<bool> {
+    let restarted = x.get_prop(x.root(), STARTED_PROP)?.is_some();
+    if !restarted {
+        x.set_prop(x.root(), STARTED_PROP, Prop::Cardinal(vec![1]))?;
+    }
+
+    Ok(restarted)
+}
+
+/// Start the services enabled on this host in dependency order, then look
+/// after them from a background thread: restarting those that ask for it and
+/// logging any failures to the penrose log. Once `restarted` in the same X
+/// session, those that are only run `once_per_session` are left out.
+pub fn start_services(services: Vec<Service>, restarted: bool) -> Result<()> {
+    validate_services(&services)?;
+
+    let enabled: Vec<Service> = services
+        .into_iter()
+        .filter(|s| s.enabled_on(&HOSTNAME) && !(restarted && s.once_per_session))
+        .collect();
+    let names: Vec<&str> = enabled.iter().map(|s| s.name.as_str()).collect();
+    // Dependencies disabled on this host can't hold anything up
+    let deps: Vec<Vec<String>> = enabled
+        .iter()
+        .map(|s| {
+            s.depends_on
+                .iter()
+                .filter(|dep| names.contains(&dep.as_str()))
+                .cloned()
+                .collect()
+        })
+        .collect();
+
+    thread::spawn(move || {
+        let mut statuses: Vec<Status> = enabled.iter().map(|_| Status::Pending).collect();
+
+        while statuses
+            .iter()
+            .any(|s| matches!(s, Status::Pending | Status::Running { .. }))
+        {
+            for ix in 0..enabled.len() {
+                let service = &enabled[ix];
+                let next = if let Status::Running {
+                    child,
+                    since,
+                    crashes,
+                } = &mut statuses[ix]
+                {
+                    check(service, child, *since, *crashes)
+                } else if let Status::Pending = statuses[ix] {
+                    ready_to_start(&deps[ix], &enabled, &statuses).map(|ready| {
+                        if ready {
+                            start(service, 0)
+                        } else {
+                            log(service, "not started as a dependency failed");
+                            Status::Failed
+                        }
+                    })
+                } else {
+                    None
+                };
+
+                if let Some(status) = next {
+                    statuses[ix] = status;
+                }
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    Ok(())
+}
+
+/// `Some(true)` once every dependency is up, `Some(false)` if any of them
+/// failed and `None` while still waiting on them.
+fn ready_to_start(deps: &[String], services: &[Service], statuses: &[Status]) -> Option<bool> {
+    let dep_statuses: Vec<&Status> = deps
+        .iter()
+        .filter_map(|dep| services.iter().position(|s| s.name == *dep))
+        .map(|ix| &statuses[ix])
+        .collect();
+
+    if dep_statuses.iter().any(|s| matches!(s, Status::Failed)) {
+        Some(false)
+    } else if dep_statuses.iter().all(|s| s.is_up()) {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+fn log(service: &Service, msg: &str) {
+    let msg = format!("service '{}' {msg}", service.name);
+    log_penrose(&msg).unwrap_or_else(|e| eprintln!("Couldn't log {msg}: {e:?}"));
+}
+
+fn start(service: &Service, crashes: u32) -> Status {
+    if service.only_if_missing {
+        match is_running(&service.program_name()) {
+            Ok(true) => return Status::Done,
+            Ok(false) => (),
+            Err(e) => log(service, &format!("couldn't check for a running copy: {e}")),
+        }
+    }
+
+    // Optional programs, such as nvidia-settings, are often not installed
+    if let Ok(None) = find_in_path(&expand_vars(&service.command)) {
+        debug!(service = service.name, "not started as it isn't on PATH");
+        return Status::Failed;
+    }
+
+    match service.spawn() {
+        Ok(child) => Status::Running {
+            child,
+            since: Instant::now(),
+            crashes,
+        },
+        Err(e) => {
+            log(service, &format!("failed to start: {e}"));
+            Status::Failed
+        }
+    }
+}
+
+/// The new status of a running service, if it has exited.
+fn check(service: &Service, child: &mut Child, since: Instant, crashes: u32) -> Option<Status> {
+    // Penrose ignores SIGCHLD so exited children are reaped for us, in which
+    // case there is no exit status to be had
+    let success = match child.try_wait() {
+        Ok(None) => return None,
+        Ok(Some(status)) => status.success(),
+        Err(e) if e.raw_os_error() == Some(libc::ECHILD) => true,
+        Err(e) => {
+            log(service, &format!("couldn't be checked on: {e}"));
+            return Some(Status::Failed);
+        }
+    };
+
+    if service.restart_on_exit {
+        let crashes = if since.elapsed() < MIN_UPTIME {
+            crashes + 1
+        } else {
+            0
+        };
+        if crashes >= MAX_CRASHES {
+            log(
+                service,
+                &format!("exited {crashes} times in a row, giving up"),
+            );
+            return Some(Status::Failed);
+        }
+        log(service, "exited, restarting");
+        Some(start(service, crashes))
+    } else if success {
+        Some(Status::Done)
+    } else {
+        log(service, "exited with an error");
+        Some(Status::Failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(name: &str, depends_on: &[&str]) -> Service {
+        Service {
+            name: name.to_string(),
+            command: name.to_string(),
+            args: Vec::new(),
+            only_if_missing: false,
+            depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+            restart_on_exit: false,
+            once_per_session: false,
+            hosts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn dependencies_in_order_are_valid() {
+        let services = [
+            service("picom", &[]),
+            service("wallpaper", &["picom"]),
+            service("bar", &["picom", "wallpaper"]),
+        ];
+
+        assert!(validate_services(&services).is_ok());
+    }
+
+    #[test]
+    fn dependency_cycles_are_rejected() {
+        let services = [
+            service("picom", &[]),
+            service("a", &["picom", "c"]),
+            service("b", &["a"]),
+            service("c", &["b"]),
+        ];
+        let err = validate_services(&services).unwrap_err().to_string();
+
+        assert!(err.contains(r#"["a", "b", "c"]"#), "{err}");
+        assert!(validate_services(&[service("a", &["a"])]).is_err());
+    }
+
+    #[test]
+    fn unknown_dependencies_and_duplicate_names_are_rejected() {
+        let err = validate_services(&[service("bar", &["picom"])])
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("unknown service 'picom'"), "{err}");
+        assert!(validate_services(&[service("a", &[]), service("a", &[])]).is_err());
+    }
+
+    #[test]
+    fn variables_are_expanded() {
+        let home = env::var("HOME").unwrap();
+
+        assert_eq!(expand_vars("$HOME/bin"), format!("{home}/bin"));
+        assert_eq!(expand_vars("${HOME}_old/x"), format!("{home}_old/x"));
+        assert_eq!(expand_vars("$HOME_old/x"), "/x");
+        assert_eq!(expand_vars("a${PENROSE_SERVICES_TEST_UNSET}b"), "ab");
+        assert_eq!(
+            expand_vars("cost: $ 5, ${unclosed"),
+            "cost: $ 5, ${unclosed"
+        );
+    }
+
+    #[test]
+    fn home_is_expanded_only_at_the_start() {
+        let home = env::var("HOME").unwrap();
+
+        assert_eq!(expand_vars("~"), home);
+        assert_eq!(expand_vars("~/bin/x"), format!("{home}/bin/x"));
+        assert_eq!(expand_vars("~user/x"), "~user/x");
+        assert_eq!(expand_vars("a/~/b"), "a/~/b");
+    }
+}
@@ -0,0 +1,360 @@
+use penrose::{
+    core::{layout::LayoutStack, Config, State},
+    pure::{geometry::Rect, Stack, Workspace},
+    x::{XConn, XConnExt},
+    Error, Result, Xid,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::PathBuf,
+};
+
+use crate::{log::LogPenroseError, workspaces::workspace_clients, SYSTEM};
+
+/// Where the session is kept between restarts: one file per X display under
+/// `$XDG_RUNTIME_DIR`, falling back to `/tmp`.
+pub fn session_path() -> PathBuf {
+    let dir = env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
+    let display = env::var("DISPLAY")
+        .unwrap_or_default()
+        .replace(['/', ':'], "");
+
+    PathBuf::from(dir).join(format!("dotpenrose-session-{display}.json"))
+}
+
+/// Enough of a client to find it again after a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionClient {
+    pub id: Xid,
+    pub pid: Option<u32>,
+    pub title: String,
+    pub process: String,
+    /// Absolute position of the client if it was floating
+    pub floating: Option<Rect>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionWorkspace {
+    pub tag: String,
+    pub layout: String,
+    pub clients: Option<Stack<SessionClient>>,
+}
+
+/// The parts of the client set that we want to survive a restart.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// The tag shown on each screen, by screen index
+    pub screens: Vec<String>,
+    pub focused_screen: usize,
+    pub workspaces: Vec<SessionWorkspace>,
+}
+
+impl Session {
+    pub fn snapshot<X: XConn>(state: &State<X>, x: &X) -> Self {
+        let cs = &state.client_set;
+        let screens = cs
+            .screens()
+            .map(|s| (s.index(), s.workspace.tag().to_string()))
+            .collect::<HashMap<_, _>>();
+        let workspaces = cs
+            .non_hidden_workspaces()
+            .map(|ws| {
+                let info = workspace_clients(&SYSTEM, state, x, ws);
+                let clients = info.clients.into_iter().map(|client| SessionClient {
+                    id: client.id,
+                    pid: client.pid,
+                    title: client.title,
+                    process: client.exe.unwrap_or_default(),
+                    floating: if client.floating {
+                        x.client_geometry(client.id).ok()
+                    } else {
+                        None
+                    },
+                });
+                let mut stack = Stack::try_from_iter(clients);
+                if let (Some(stack), Some(focus)) = (&mut stack, ws.focus()) {
+                    stack.focus_element_by(|c| c.id == *focus);
+                }
+
+                SessionWorkspace {
+                    tag: ws.tag().to_string(),
+                    layout: ws.layout_name(),
+                    clients: stack,
+                }
+            })
+            .collect();
+
+        Self {
+            screens: (0..screens.len())
+                .map(|ix| screens.get(&ix).cloned().unwrap_or_default())
+                .collect(),
+            focused_screen: cs.current_screen().index(),
+            workspaces,
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| Error::Custom(format!("unable to serialize session: {e}")))?;
+
+        Ok(fs::write(session_path(), json)?)
+    }
+
+    /// Read and remove the saved session, if there is one.
+    pub fn take_saved() -> Result<Option<Self>> {
+        let path = session_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = fs::read_to_string(&path)?;
+        fs::remove_file(&path)?;
+
+        serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| Error::Custom(format!("invalid session file: {e}")))
+    }
+
+    /// Put the clients we can find back where they were. Clients that can't be
+    /// matched against the saved session are left where they are.
+    pub fn restore<X: XConn>(self, state: &mut State<X>, x: &X) -> Result<()> {
+        let matched = self.match_clients(state, x);
+        let claimed: HashSet<Xid> = matched.values().copied().collect();
+        let saved: HashMap<&str, &SessionWorkspace> = self
+            .workspaces
+            .iter()
+            .map(|ws| (ws.tag.as_str(), ws))
+            .collect();
+        let cs = &mut state.client_set;
+        let visible_tags: HashSet<String> = cs
+            .non_hidden_workspaces()
+            .map(|ws| ws.tag().to_string())
+            .collect();
+
+        for ws in cs.workspaces_mut() {
+            if !visible_tags.contains(ws.tag()) {
+                continue;
+            }
+            let saved_ws = saved.get(ws.tag());
+            let saved_stack = saved_ws.and_then(|s| s.clients.as_ref());
+            let mut clients: Vec<Xid> = saved_stack
+                .map(|s| {
+                    s.iter()
+                        .filter_map(|c| matched.get(&c.id).copied())
+                        .collect()
+                })
+                .unwrap_or_default();
+            clients.extend(ws.clients().filter(|c| !claimed.contains(c)));
+
+            let focus = saved_stack
+                .and_then(|s| matched.get(&s.focused().id))
+                .or(ws.focus().filter(|c| !claimed.contains(c)))
+                .copied();
+            let mut stack = Stack::try_from_iter(clients);
+            if let (Some(stack), Some(focus)) = (&mut stack, focus) {
+                stack.focus_element(&focus);
+            }
+
+            let layouts = ws.set_available_layouts(LayoutStack::default());
+            *ws = Workspace::new(ws.id(), ws.tag(), layouts, stack);
+            if let Some(saved_ws) = saved_ws {
+                ws.set_layout_by_name(&saved_ws.layout);
+            }
+        }
+
+        // Floating positions are relative to the screen a client is on, so
+        // each tag needs to be visible while we float its clients.
+        for client in self
+            .workspaces
+            .iter()
+            .flat_map(|ws| ws.clients.iter().flatten())
+        {
+            let Some(&id) = matched.get(&client.id) else {
+                continue;
+            };
+            match client.floating {
+                Some(r) => {
+                    if let Some(tag) = cs.tag_for_client(&id).map(String::from) {
+                        cs.pull_tag_to_screen(tag);
+                    }
+                    cs.float(id, r)
+                        .log_err(&format!("Couldn't restore floating client {id}"));
+                }
+                None => {
+                    cs.sink(&id);
+                }
+            }
+        }
+
+        for (ix, tag) in self.screens.iter().enumerate() {
+            cs.focus_screen(ix);
+            if cs.current_screen().index() == ix {
+                cs.pull_tag_to_screen(tag);
+            }
+        }
+        cs.focus_screen(self.focused_screen);
+
+        x.refresh(state)
+    }
+
+    /// Map saved client ids to the ids of the clients we are now managing.
+    ///
+    /// Ids are kept when only the window manager restarts, so they are tried
+    /// first (as long as the pid agrees), then pid and title, then process
+    /// name and title.
+    fn match_clients<X: XConn>(&self, state: &State<X>, x: &X) -> HashMap<Xid, Xid> {
+        let current = Self::snapshot(state, x);
+        let mut unclaimed: Vec<&SessionClient> = current
+            .workspaces
+            .iter()
+            .flat_map(|ws| ws.clients.iter().flatten())
+            .collect();
+        let saved: Vec<&SessionClient> = self
+            .workspaces
+            .iter()
+            .flat_map(|ws| ws.clients.iter().flatten())
+            .collect();
+
+        let same_id = |s: &SessionClient, c: &SessionClient| {
+            s.id == c.id && (s.pid.is_none() || c.pid.is_none() || s.pid == c.pid)
+        };
+        let same_pid = |s: &SessionClient, c: &SessionClient| {
+            s.pid.is_some() && s.pid == c.pid && s.title == c.title
+        };
+        let same_process = |s: &SessionClient, c: &SessionClient| {
+            !s.process.is_empty() && s.process == c.process && s.title == c.title
+        };
+
+        let mut matched = HashMap::new();
+        for matches in [&same_id as &dyn Fn(_, _) -> bool, &same_pid, &same_process] {
+            for s in saved.iter() {
+                if matched.contains_key(&s.id) {
+                    continue;
+                }
+                if let Some(ix) = unclaimed.iter().position(|c| matches(s, c)) {
+                    matched.insert(s.id, unclaimed.remove(ix).id);
+                }
+            }
+        }
+
+        matched
+    }
+}
+
+/// Restore any saved session once existing clients have been managed at
+/// startup. Save the session with [Session::snapshot] and [Session::save]
+/// before exiting.
+pub fn add_session_hooks<X>(mut config: Config<X>) -> Config<X>
+where
+    X: XConn + 'static,
+{
+    let mut pending = Session::take_saved()
+        .log_err("Couldn't read saved session")
+        .flatten();
+
+    // The first refresh comes at the end of managing existing clients
+    config.compose_or_set_refresh_hook(move |state: &mut State<X>, x: &X| match pending.take() {
+        Some(session) => session.restore(state, x),
+        None => Ok(()),
+    });
+
+    config
+}
+
+/// Save the current session, logging any errors.
+pub fn save_session<X: XConn>(state: &State<X>, x: &X) {
+    Session::snapshot(state, x)
+        .save()
+        .log_err("Couldn't save session");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeConn;
+    use std::process;
+
+    // Well above any real pid, so that none of them has a process
+    const PID: u32 = 4_100_000;
+
+    fn conn() -> FakeConn {
+        FakeConn::new(vec![Rect::new(0, 0, 1000, 800)])
+    }
+
+    fn saved(id: u32, pid: Option<u32>, title: &str, process: &str) -> SessionClient {
+        SessionClient {
+            id: Xid::from(id),
+            pid,
+            title: title.to_string(),
+            process: process.to_string(),
+            floating: None,
+        }
+    }
+
+    fn session(clients: Vec<SessionClient>) -> Session {
+        Session {
+            screens: vec!["1".to_string()],
+            focused_screen: 0,
+            workspaces: vec![SessionWorkspace {
+                tag: "1".to_string(),
+                layout: String::new(),
+                clients: Stack::try_from_iter(clients),
+            }],
+        }
+    }
+
+    fn matches(session: &Session, x: &FakeConn) -> HashMap<u32, u32> {
+        let mut state = x.state(&["1"]);
+        for &id in x.props.keys() {
+            state.client_set.insert(id);
+        }
+
+        session
+            .match_clients(&state, x)
+            .into_iter()
+            .map(|(saved, current)| (*saved, *current))
+            .collect()
+    }
+
+    #[test]
+    fn ids_are_kept_while_the_pid_agrees() {
+        let x = conn()
+            .with_client(1, &[], "renamed", PID)
+            .with_client(2, &[], "editor", PID + 1);
+        let session = session(vec![saved(1, Some(PID), "shell", "")]);
+
+        assert_eq!(matches(&session, &x), HashMap::from([(1, 1)]));
+    }
+
+    #[test]
+    fn reused_id_falls_back_to_pid_and_title() {
+        // Window 1 now belongs to another program and the editor is window 2
+        let x = conn()
+            .with_client(1, &[], "browser", PID + 1)
+            .with_client(2, &[], "editor", PID);
+        let session = session(vec![saved(1, Some(PID), "editor", "")]);
+
+        assert_eq!(matches(&session, &x), HashMap::from([(1, 2)]));
+    }
+
+    #[test]
+    fn pid_needs_the_same_title() {
+        let x = conn().with_client(2, &[], "other tab", PID);
+        let session = session(vec![saved(1, Some(PID), "editor", "")]);
+
+        assert!(matches(&session, &x).is_empty());
+    }
+
+    #[test]
+    fn restarted_program_is_matched_by_process_and_title() {
+        let exe = env::current_exe().unwrap();
+        let name = exe.file_name().unwrap().to_string_lossy();
+        let x = conn()
+            .with_client(5, &[], "other", process::id())
+            .with_client(6, &[], "editor", process::id());
+        let session = session(vec![saved(1, Some(PID), "editor", &name)]);
+
+        assert_eq!(matches(&session, &x), HashMap::from([(1, 6)]));
+    }
+}
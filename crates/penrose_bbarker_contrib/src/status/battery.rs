@@ -0,0 +1,212 @@
+use std::{fs, path::Path};
+
+use super::read_trimmed;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeState {
+    Charging,
+    Discharging,
+    Full,
+    NotCharging,
+    Unknown,
+}
+
+impl ChargeState {
+    fn parse(status: &str) -> Self {
+        match status {
+            "Charging" => ChargeState::Charging,
+            "Discharging" => ChargeState::Discharging,
+            "Full" => ChargeState::Full,
+            "Not charging" => ChargeState::NotCharging,
+            _ => ChargeState::Unknown,
+        }
+    }
+}
+
+/// The combined charge of the system's batteries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Battery {
+    pub percent: u8,
+    pub state: ChargeState,
+}
+
+impl Battery {
+    /// Read every system battery in `sys/class/power_supply` under `root`,
+    /// which is `/` outside of tests. Batteries in peripherals such as mice
+    /// are left out. Returns `None` if there are no batteries.
+    pub fn read(root: &Path) -> Option<Self> {
+        let supplies = fs::read_dir(root.join("sys/class/power_supply")).ok()?;
+
+        let mut now = 0;
+        let mut full = 0;
+        let mut states = Vec::new();
+        for supply in supplies.flatten().map(|entry| entry.path()) {
+            let read = |name: &str| read_trimmed(&supply.join(name));
+            let is_system_battery = read("type").as_deref() == Some("Battery")
+                && read("scope").as_deref() != Some("Device");
+            if !is_system_battery {
+                continue;
+            }
+
+            // Batteries report energy (µWh), charge (µAh) or only a
+            // percentage, which is weighted as if it were a tiny battery
+            let number = |name: &str| read(name)?.parse::<u64>().ok();
+            let charge = number("energy_now")
+                .zip(number("energy_full"))
+                .or_else(|| number("charge_now").zip(number("charge_full")))
+                .or_else(|| number("capacity").map(|c| (c, 100)));
+            if let Some((n, f)) = charge {
+                now += n;
+                full += f;
+                states.push(ChargeState::parse(&read("status").unwrap_or_default()));
+            }
+        }
+
+        if full == 0 {
+            return None;
+        }
+
+        let state = if states.contains(&ChargeState::Charging) {
+            ChargeState::Charging
+        } else if states.contains(&ChargeState::Discharging) {
+            ChargeState::Discharging
+        } else if states.iter().all(|&s| s == ChargeState::Full) {
+            ChargeState::Full
+        } else if states.contains(&ChargeState::NotCharging) {
+            ChargeState::NotCharging
+        } else {
+            ChargeState::Unknown
+        };
+
+        Some(Self {
+            percent: (now * 100 / full).min(100) as u8,
+            state,
+        })
+    }
+
+    pub fn is_charging(&self) -> bool {
+        self.state == ChargeState::Charging
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match (self.state, self.percent) {
+            (ChargeState::Charging, _) => "\u{f1e6}",
+            (ChargeState::Full, _) | (_, 90..) => "\u{f240}",
+            (_, 70..) => "\u{f241}",
+            (_, 50..) => "\u{f242}",
+            (_, 20..) => "\u{f243}",
+            _ => "\u{f244}",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn supply(root: &TempDir, name: &str, files: &[(&str, &str)]) {
+        for (file, contents) in files {
+            root.write(format!("sys/class/power_supply/{name}/{file}"), contents);
+        }
+    }
+
+    #[test]
+    fn energy_is_read() {
+        let root = TempDir::new();
+        supply(
+            &root,
+            "BAT0",
+            &[
+                ("type", "Battery\n"),
+                ("status", "Discharging\n"),
+                ("energy_now", "30000000\n"),
+                ("energy_full", "40000000\n"),
+            ],
+        );
+        supply(&root, "AC", &[("type", "Mains\n"), ("online", "0\n")]);
+
+        assert_eq!(
+            Battery::read(root.path()),
+            Some(Battery {
+                percent: 75,
+                state: ChargeState::Discharging,
+            })
+        );
+    }
+
+    #[test]
+    fn peripherals_are_left_out() {
+        let root = TempDir::new();
+        supply(
+            &root,
+            "BAT0",
+            &[
+                ("type", "Battery\n"),
+                ("status", "Full\n"),
+                ("energy_now", "40000000\n"),
+                ("energy_full", "40000000\n"),
+            ],
+        );
+        supply(
+            &root,
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery\n"),
+                ("scope", "Device\n"),
+                ("status", "Discharging\n"),
+                ("capacity", "5\n"),
+            ],
+        );
+
+        assert_eq!(
+            Battery::read(root.path()),
+            Some(Battery {
+                percent: 100,
+                state: ChargeState::Full,
+            })
+        );
+    }
+
+    #[test]
+    fn charge_and_capacity_only_batteries_are_combined() {
+        let root = TempDir::new();
+        supply(
+            &root,
+            "BAT0",
+            &[
+                ("type", "Battery\n"),
+                ("status", "Not charging\n"),
+                ("charge_now", "150\n"),
+                ("charge_full", "200\n"),
+            ],
+        );
+        supply(
+            &root,
+            "BAT1",
+            &[
+                ("type", "Battery\n"),
+                ("status", "Charging\n"),
+                ("capacity", "50\n"),
+            ],
+        );
+
+        // (150 + 50) / (200 + 100)
+        assert_eq!(
+            Battery::read(root.path()),
+            Some(Battery {
+                percent: 66,
+                state: ChargeState::Charging,
+            })
+        );
+    }
+
+    #[test]
+    fn no_batteries() {
+        let root = TempDir::new();
+        supply(&root, "AC", &[("type", "Mains\n"), ("online", "1\n")]);
+
+        assert_eq!(Battery::read(root.path()), None);
+        assert_eq!(Battery::read(&root.path().join("missing")), None);
+    }
+}
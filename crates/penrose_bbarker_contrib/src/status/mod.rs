@@ -0,0 +1,44 @@
+pub mod battery;
+pub mod system;
+pub mod volume;
+pub mod wifi;
+
+use std::{fs, path::Path};
+
+/// How much attention a reading needs, which the bar shows with its color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Level {
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Level {
+    /// The level of a reading where lower is worse.
+    pub fn when_low(value: u8, warning: u8, critical: u8) -> Self {
+        if value <= critical {
+            Level::Critical
+        } else if value <= warning {
+            Level::Warning
+        } else {
+            Level::Normal
+        }
+    }
+
+    /// The level of a reading where higher is worse.
+    pub fn when_high<T: PartialOrd>(value: T, warning: T, critical: T) -> Self {
+        if value >= critical {
+            Level::Critical
+        } else if value >= warning {
+            Level::Warning
+        } else {
+            Level::Normal
+        }
+    }
+}
+
+/// The trimmed contents of a small sysfs or procfs file.
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
@@ -0,0 +1,119 @@
+use once_cell::sync::Lazy;
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+use sysinfo::{Components, CpuRefreshKind, Disks, MemoryRefreshKind, RefreshKind, System};
+
+/// How often the shared snapshot is refreshed.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskUsage {
+    pub mount_point: PathBuf,
+    /// In bytes
+    pub used: u64,
+    pub total: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Temperature {
+    /// The hwmon sensor's label, such as "coretemp Package id 0"
+    pub label: String,
+    pub celsius: f32,
+}
+
+/// System load at a point in time. Memory and swap are in bytes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemSnapshot {
+    /// Usage across all cores, as a percentage
+    pub cpu: f32,
+    pub cores: Vec<f32>,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub swap_used: u64,
+    pub swap_total: u64,
+    /// Over the last 1, 5 and 15 minutes
+    pub load: [f64; 3],
+    pub disks: Vec<DiskUsage>,
+    pub temperatures: Vec<Temperature>,
+}
+
+/// The percentage of `total` that `used` is, or 0 if `total` is.
+pub fn percent(used: u64, total: u64) -> u8 {
+    (used * 100).checked_div(total).unwrap_or(0).min(100) as u8
+}
+
+static SNAPSHOT: Lazy<Arc<RwLock<SystemSnapshot>>> = Lazy::new(|| {
+    let snapshot = Arc::new(RwLock::new(SystemSnapshot::default()));
+    let shared = snapshot.clone();
+    thread::spawn(move || refresh_forever(&shared));
+
+    snapshot
+});
+
+/// The latest system snapshot. Every caller shares one sysinfo instance,
+/// refreshed in the background every couple of seconds from the first call
+/// on, so this is cheap to call from as many bar widgets as need it. CPU
+/// usage reads as zero until the second refresh.
+pub fn system_snapshot() -> SystemSnapshot {
+    SNAPSHOT
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+fn refresh_forever(shared: &RwLock<SystemSnapshot>) {
+    let mut system = System::new_with_specifics(
+        RefreshKind::nothing()
+            .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
+            .with_memory(MemoryRefreshKind::everything()),
+    );
+    let mut disks = Disks::new_with_refreshed_list();
+    let mut components = Components::new_with_refreshed_list();
+
+    loop {
+        // CPU usage is measured between one refresh and the next
+        system.refresh_cpu_usage();
+        system.refresh_memory();
+        disks.refresh(true);
+        components.refresh(true);
+        let load = System::load_average();
+
+        let snapshot = SystemSnapshot {
+            cpu: system.global_cpu_usage(),
+            cores: system.cpus().iter().map(|cpu| cpu.cpu_usage()).collect(),
+            memory_used: system.used_memory(),
+            memory_total: system.total_memory(),
+            swap_used: system.used_swap(),
+            swap_total: system.total_swap(),
+            load: [load.one, load.five, load.fifteen],
+            disks: disks
+                .list()
+                .iter()
+                .map(|disk| DiskUsage {
+                    mount_point: disk.mount_point().to_path_buf(),
+                    used: disk.total_space().saturating_sub(disk.available_space()),
+                    total: disk.total_space(),
+                })
+                .collect(),
+            temperatures: components
+                .list()
+                .iter()
+                .filter_map(|c| {
+                    Some(Temperature {
+                        label: c.label().to_string(),
+                        celsius: c.temperature()?,
+                    })
+                })
+                .collect(),
+        };
+        *shared
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = snapshot;
+
+        thread::sleep(REFRESH_INTERVAL);
+    }
+}
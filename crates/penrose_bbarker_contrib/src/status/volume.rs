@@ -0,0 +1,145 @@
+use penrose::{Error, Result};
+use std::{fs::File, io, mem, os::fd::AsRawFd};
+
+// From sound/asound.h
+const SNDRV_CTL_ELEM_IFACE_MIXER: i32 = 2;
+const SNDRV_CTL_ELEM_TYPE_BOOLEAN: i32 = 1;
+const SNDRV_CTL_ELEM_TYPE_INTEGER: i32 = 2;
+const SNDRV_CTL_IOCTL_ELEM_INFO: u8 = 0x11;
+const SNDRV_CTL_IOCTL_ELEM_READ: u8 = 0x12;
+
+/// The volume of a mixer control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volume {
+    /// Averaged over the control's channels, as amixer shows it
+    pub percent: u8,
+    pub muted: bool,
+}
+
+impl Volume {
+    /// Read the playback volume and mute switch of the mixer control named
+    /// `control` (such as "Master") on ALSA sound card `card`. Under
+    /// PipeWire or PulseAudio this is the hardware volume, which they drive
+    /// for the default output.
+    pub fn read(card: u32, control: &str) -> Result<Self> {
+        let device = File::open(format!("/dev/snd/controlC{card}"))?;
+
+        let (volume, info) = read_elem(&device, &format!("{control} Playback Volume"))?;
+        if info.kind != SNDRV_CTL_ELEM_TYPE_INTEGER {
+            return Err(Error::Custom(format!("{control} isn't a volume control")));
+        }
+        let (min, max) = (info.value[0], info.value[1]);
+        let range = (max - min).max(1) as f64;
+        let percent = volume
+            .iter()
+            .map(|&v| (v - min) as f64 * 100.0 / range)
+            .sum::<f64>()
+            / volume.len().max(1) as f64;
+
+        // Not every control can be muted
+        let muted = match read_elem(&device, &format!("{control} Playback Switch")) {
+            Ok((switch, info)) if info.kind == SNDRV_CTL_ELEM_TYPE_BOOLEAN => {
+                switch.iter().all(|&on| on == 0)
+            }
+            _ => false,
+        };
+
+        Ok(Self {
+            percent: percent.round().clamp(0.0, 100.0) as u8,
+            muted,
+        })
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match (self.muted, self.percent) {
+            (true, _) | (_, 0) => "\u{f075f}",
+            (_, 67..) => "\u{f057e}",
+            (_, 34..) => "\u{f0580}",
+            _ => "\u{f057f}",
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElemId {
+    numid: u32,
+    iface: i32,
+    device: u32,
+    subdevice: u32,
+    name: [u8; 44],
+    index: u32,
+}
+
+#[repr(C)]
+struct ElemInfo {
+    id: ElemId,
+    kind: i32,
+    access: u32,
+    count: u32,
+    owner: libc::pid_t,
+    /// For integer controls this starts with the min, max and step
+    value: [libc::c_long; 128 / mem::size_of::<libc::c_long>()],
+    reserved: [u8; 64],
+}
+
+#[repr(C)]
+struct ElemValue {
+    id: ElemId,
+    indirect: u32,
+    value: [libc::c_long; 128],
+    reserved: [u8; 128],
+}
+
+/// `_IOWR('U', nr, T)`
+const fn ioctl_rw<T>(nr: u8) -> u64 {
+    (3 << 30) | ((mem::size_of::<T>() as u64) << 16) | ((b'U' as u64) << 8) | nr as u64
+}
+
+/// The values of the mixer element called `name`, along with its info.
+fn read_elem(device: &File, name: &str) -> Result<(Vec<libc::c_long>, ElemInfo)> {
+    let fd = device.as_raw_fd();
+
+    // SAFETY: these are plain C structs for which all zeroes is valid
+    let mut info: ElemInfo = unsafe { mem::zeroed() };
+    info.id.iface = SNDRV_CTL_ELEM_IFACE_MIXER;
+    let bytes = name.as_bytes();
+    if bytes.len() >= info.id.name.len() {
+        return Err(Error::Custom(format!(
+            "{name} is too long for a control name"
+        )));
+    }
+    info.id.name[..bytes.len()].copy_from_slice(bytes);
+
+    // SAFETY: info is the struct that this ioctl expects and outlives the call
+    let res = unsafe {
+        libc::ioctl(
+            fd,
+            ioctl_rw::<ElemInfo>(SNDRV_CTL_IOCTL_ELEM_INFO) as _,
+            &mut info as *mut ElemInfo,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    // SAFETY: as above
+    let mut value: ElemValue = unsafe { mem::zeroed() };
+    value.id = info.id;
+    // SAFETY: value is the struct that this ioctl expects and outlives the call
+    let res = unsafe {
+        libc::ioctl(
+            fd,
+            ioctl_rw::<ElemValue>(SNDRV_CTL_IOCTL_ELEM_READ) as _,
+            &mut value as *mut ElemValue,
+        )
+    };
+    if res < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let count = (info.count as usize).min(value.value.len());
+    let values = value.value[..count].to_vec();
+
+    Ok((values, info))
+}
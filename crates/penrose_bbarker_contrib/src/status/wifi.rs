@@ -0,0 +1,273 @@
+use std::{
+    fs,
+    io::{Error, Result},
+    mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+};
+
+use super::read_trimmed;
+
+/// Link quality as reported by most drivers is out of 70.
+const MAX_LINK_QUALITY: f64 = 70.0;
+
+/// A connected wireless interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wifi {
+    pub interface: String,
+    /// Link quality as a percentage
+    pub quality: u8,
+}
+
+impl Wifi {
+    /// The first wireless interface in `proc/net/wireless` under `root`,
+    /// which is `/` outside of tests, that `sys/class/net` has as up.
+    /// Returns `None` when not connected.
+    pub fn read(root: &Path) -> Option<Self> {
+        let wireless = fs::read_to_string(root.join("proc/net/wireless")).ok()?;
+
+        // After two header lines, each line is "wlan0: 0000   54.  -56. ..."
+        wireless.lines().skip(2).find_map(|line| {
+            let (interface, stats) = line.split_once(':')?;
+            let interface = interface.trim();
+            let link: f64 = stats
+                .split_whitespace()
+                .nth(1)?
+                .trim_end_matches('.')
+                .parse()
+                .ok()?;
+            let operstate =
+                read_trimmed(&root.join(format!("sys/class/net/{interface}/operstate")));
+            if operstate.as_deref() != Some("up") {
+                return None;
+            }
+
+            Some(Self {
+                interface: interface.to_string(),
+                quality: (link * 100.0 / MAX_LINK_QUALITY).clamp(0.0, 100.0).round() as u8,
+            })
+        })
+    }
+
+    /// Whether `sys/class/net` under `root` has any wireless interfaces,
+    /// connected or not.
+    pub fn has_interface(root: &Path) -> bool {
+        fs::read_dir(root.join("sys/class/net"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .any(|entry| entry.path().join("wireless").is_dir())
+    }
+
+    /// The name of the network, asked of the kernel's nl80211 interface.
+    pub fn ssid(&self) -> Option<String> {
+        nl80211_ssid(&self.interface).ok().flatten()
+    }
+}
+
+// Generic netlink constants from linux/netlink.h, linux/genetlink.h and
+// linux/nl80211.h
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 1;
+const NLMSG_HDRLEN: usize = 16;
+const GENL_HDRLEN: usize = 4;
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+const NL80211_CMD_GET_INTERFACE: u8 = 5;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_SSID: u16 = 52;
+
+/// The SSID that `interface` is connected to, if any.
+fn nl80211_ssid(interface: &str) -> Result<Option<String>> {
+    let ifindex: u32 = read_trimmed(Path::new(&format!("/sys/class/net/{interface}/ifindex")))
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| Error::other(format!("no ifindex for {interface}")))?;
+
+    let socket = NetlinkSocket::open()?;
+    let family = socket.request(
+        GENL_ID_CTRL,
+        CTRL_CMD_GETFAMILY,
+        &[(CTRL_ATTR_FAMILY_NAME, b"nl80211\0")],
+    )?;
+    let family = attribute(&family, CTRL_ATTR_FAMILY_ID)
+        .and_then(|id| Some(u16::from_ne_bytes(id.get(..2)?.try_into().ok()?)))
+        .ok_or_else(|| Error::other("nl80211 is not available"))?;
+
+    let info = socket.request(
+        family,
+        NL80211_CMD_GET_INTERFACE,
+        &[(NL80211_ATTR_IFINDEX, &ifindex.to_ne_bytes())],
+    )?;
+
+    Ok(attribute(&info, NL80211_ATTR_SSID).map(|ssid| String::from_utf8_lossy(ssid).into_owned()))
+}
+
+struct NetlinkSocket(OwnedFd);
+
+impl NetlinkSocket {
+    fn open() -> Result<Self> {
+        // SAFETY: a plain socket(2) call, whose result is checked before use
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_GENERIC,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        // SAFETY: fd is a freshly opened socket that nothing else owns
+        let socket = Self(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        // Don't hold up the bar if the kernel never answers
+        let timeout = libc::timeval {
+            tv_sec: 1,
+            tv_usec: 0,
+        };
+        // SAFETY: timeout is a timeval that outlives the call
+        let res = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &timeout as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(socket)
+    }
+
+    /// Send a generic netlink request and return the attributes of the reply.
+    fn request(&self, family: u16, cmd: u8, attrs: &[(u16, &[u8])]) -> Result<Vec<u8>> {
+        let mut msg = vec![0; NLMSG_HDRLEN + GENL_HDRLEN];
+        for (kind, payload) in attrs {
+            msg.extend(((4 + payload.len()) as u16).to_ne_bytes());
+            msg.extend(kind.to_ne_bytes());
+            msg.extend(*payload);
+            msg.resize(align(msg.len()), 0);
+        }
+        let len = msg.len() as u32;
+        msg[0..4].copy_from_slice(&len.to_ne_bytes());
+        msg[4..6].copy_from_slice(&family.to_ne_bytes());
+        msg[6..8].copy_from_slice(&NLM_F_REQUEST.to_ne_bytes());
+        msg[8..12].copy_from_slice(&1u32.to_ne_bytes());
+        msg[NLMSG_HDRLEN] = cmd;
+        msg[NLMSG_HDRLEN + 1] = 1;
+
+        let fd = self.0.as_raw_fd();
+        // SAFETY: msg is valid for reads of its length
+        let sent = unsafe { libc::send(fd, msg.as_ptr() as *const libc::c_void, msg.len(), 0) };
+        if sent < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let mut reply = vec![0u8; 8192];
+        // SAFETY: reply is valid for writes of its length
+        let received =
+            unsafe { libc::recv(fd, reply.as_mut_ptr() as *mut libc::c_void, reply.len(), 0) };
+        if received < 0 {
+            return Err(Error::last_os_error());
+        }
+        reply.truncate(received as usize);
+
+        let header = reply
+            .get(..NLMSG_HDRLEN + GENL_HDRLEN)
+            .ok_or_else(|| Error::other("short netlink reply"))?;
+        let len = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = u16::from_ne_bytes([header[4], header[5]]);
+        if kind == NLMSG_ERROR {
+            return Err(Error::other("netlink request failed"));
+        }
+
+        reply
+            .get(NLMSG_HDRLEN + GENL_HDRLEN..len)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| Error::other("short netlink reply"))
+    }
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// The payload of the first attribute of type `kind` in `attrs`.
+fn attribute(attrs: &[u8], kind: u16) -> Option<&[u8]> {
+    let mut rest = attrs;
+    while rest.len() >= 4 {
+        let len = u16::from_ne_bytes([rest[0], rest[1]]) as usize;
+        // The top bits are flags rather than part of the type
+        let this_kind = u16::from_ne_bytes([rest[2], rest[3]]) & 0x3fff;
+        if len < 4 || len > rest.len() {
+            return None;
+        }
+        if this_kind == kind {
+            return Some(&rest[4..len]);
+        }
+        rest = &rest[align(len).min(rest.len())..];
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const WIRELESS: &str = "\
+Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+wlan0: 0000   49.  -61.  -256        0      0      0      0     17        0
+";
+
+    fn interface(root: &TempDir, name: &str, operstate: &str) {
+        root.write(format!("sys/class/net/{name}/operstate"), operstate);
+        fs::create_dir_all(root.path().join(format!("sys/class/net/{name}/wireless"))).unwrap();
+    }
+
+    #[test]
+    fn connected_interface_is_read() {
+        let root = TempDir::new();
+        root.write("proc/net/wireless", WIRELESS);
+        interface(&root, "wlan0", "up\n");
+
+        assert_eq!(
+            Wifi::read(root.path()),
+            Some(Wifi {
+                interface: "wlan0".to_string(),
+                quality: 70,
+            })
+        );
+        assert!(Wifi::has_interface(root.path()));
+    }
+
+    #[test]
+    fn interface_that_is_down_is_not_connected() {
+        let root = TempDir::new();
+        root.write("proc/net/wireless", WIRELESS);
+        interface(&root, "wlan0", "down\n");
+
+        assert_eq!(Wifi::read(root.path()), None);
+        assert!(Wifi::has_interface(root.path()));
+    }
+
+    #[test]
+    fn wired_only_has_no_wireless_interface() {
+        let root = TempDir::new();
+        root.write(
+            "proc/net/wireless",
+            &WIRELESS[..WIRELESS.find("wlan0").unwrap()],
+        );
+        root.write("sys/class/net/eth0/operstate", "up\n");
+
+        assert_eq!(Wifi::read(root.path()), None);
+        assert!(!Wifi::has_interface(root.path()));
+    }
+}
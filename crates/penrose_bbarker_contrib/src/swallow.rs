@@ -0,0 +1,338 @@
+use penrose::{
+    core::{layout::LayoutStack, ClientSet, Config, State},
+    pure::{geometry::Rect, Stack, Workspace},
+    x::{XConn, XConnExt, XEvent},
+    Result, Xid,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::{
+    log::LogPenroseError,
+    rules::{ClientMatchInfo, WindowMatch},
+    workspaces::client_pids,
+    SYSTEM,
+};
+
+/// Which windows give up their place to the programs started from them.
+///
+/// ```toml
+/// [swallow]
+/// parents = [{ class = "Alacritty" }]
+/// exempt = [{ exe = "xev" }]
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwallowConfig {
+    /// Windows, normally terminals, that are hidden while a program they
+    /// started has a window open. A window that itself matches one of these
+    /// never swallows, so a terminal opened from a terminal sits beside it.
+    pub parents: Vec<WindowMatch>,
+    /// Windows that are never put in the place of their parent
+    pub exempt: Vec<WindowMatch>,
+}
+
+impl Default for SwallowConfig {
+    fn default() -> Self {
+        Self {
+            parents: vec![WindowMatch::Class("Alacritty".to_string())],
+            exempt: Vec::new(),
+        }
+    }
+}
+
+impl SwallowConfig {
+    fn any_match(matchers: &[WindowMatch], info: &ClientMatchInfo) -> bool {
+        matchers.iter().any(|m| m.matches(info))
+    }
+
+    fn match_info<X: XConn>(&self, x: &X, client: Xid) -> ClientMatchInfo {
+        let with_exe = self
+            .parents
+            .iter()
+            .chain(&self.exempt)
+            .any(|m| matches!(m, WindowMatch::Exe(_)));
+
+        ClientMatchInfo::new(x, client, with_exe)
+    }
+}
+
+/// The invisible workspace that swallowed windows are kept on while hidden, so
+/// that they stay managed. Which child swallowed them isn't saved, so after a
+/// restart they come back as ordinary windows on the current workspace.
+pub const SWALLOWED_TAG: &str = "SWALLOWED";
+
+/// A swallowed parent and, if it was floating, where it was.
+#[derive(Debug, Clone, Copy)]
+struct Swallowed {
+    parent: Xid,
+    floating: Option<Rect>,
+}
+
+/// Swallowed parents by the child that took their place.
+#[derive(Debug, Default)]
+struct SwallowState(HashMap<Xid, Swallowed>);
+
+/// If `child` was started from a managed window matching one of the parents
+/// in `config`, found by walking up its process tree, put `child` in that
+/// window's place and hide it until `child` is closed. Intended to be run
+/// from a manage hook, with [add_swallow_hooks] bringing parents back.
+pub fn swallow<X: XConn>(
+    config: &SwallowConfig,
+    child: Xid,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    if config.parents.is_empty() || !state.client_set.contains_tag(SWALLOWED_TAG) {
+        return Ok(());
+    }
+    let Some(pid) = first_pid(x, child) else {
+        return Ok(());
+    };
+    let info = config.match_info(x, child);
+    if SwallowConfig::any_match(&config.parents, &info)
+        || SwallowConfig::any_match(&config.exempt, &info)
+    {
+        return Ok(());
+    }
+
+    let windows: HashMap<u32, Xid> = state
+        .client_set
+        .clients()
+        .filter(|&&c| c != child)
+        .filter_map(|&c| Some((first_pid(x, c)?, c)))
+        .collect();
+    let parent = SYSTEM
+        .ancestors(pid)
+        .into_iter()
+        .filter_map(|pid| windows.get(&pid).copied())
+        .find(|&parent| SwallowConfig::any_match(&config.parents, &config.match_info(x, parent)));
+    let Some(parent) = parent else {
+        return Ok(());
+    };
+
+    hide_parent(state, parent, child, x);
+
+    Ok(())
+}
+
+/// Keep swallowed parents on a hidden workspace and restore them in place of
+/// their child once it is closed.
+pub fn add_swallow_hooks<X>(mut config: Config<X>) -> Config<X>
+where
+    X: XConn + 'static,
+{
+    config.compose_or_set_startup_hook(|state: &mut State<X>, _: &X| {
+        state.client_set.add_invisible_workspace(SWALLOWED_TAG)
+    });
+
+    config.compose_or_set_event_hook(|event: &XEvent, state: &mut State<X>, x: &X| {
+        match event {
+            // A client unmapping itself is about to be unmanaged; one that we
+            // hid ourselves has already left the mapped clients.
+            XEvent::UnmapNotify(id) if state.mapped_clients().contains(id) => {
+                restore_parent(*id, state, x)?
+            }
+            XEvent::Destroy(id) => restore_parent(*id, state, x)?,
+            _ => (),
+        }
+
+        Ok(true)
+    });
+
+    config
+}
+
+fn hide_parent<X: XConn>(state: &mut State<X>, parent: Xid, child: Xid, x: &X) {
+    let floating = if state.client_set.is_floating(&parent) {
+        x.client_geometry(parent).ok()
+    } else {
+        None
+    };
+
+    let cs = &mut state.client_set;
+    replace_client(cs, parent, child);
+    if let Some(ws) = cs.workspace_mut(SWALLOWED_TAG) {
+        let clients: Vec<Xid> = ws.clients().copied().chain([parent]).collect();
+        rebuild_workspace(ws, clients, Some(parent));
+    }
+    if let Some(r) = floating {
+        cs.float(child, r)
+            .log_err("Couldn't float a swallowing window");
+    }
+
+    state
+        .extension_or_default::<SwallowState>()
+        .borrow_mut()
+        .0
+        .insert(child, Swallowed { parent, floating });
+}
+
+fn restore_parent<X: XConn>(child: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    let swallowed = {
+        let extension = state.extension_or_default::<SwallowState>();
+        let mut swallowed = extension.borrow_mut();
+        // A parent closed while hidden has nothing to come back to
+        swallowed.0.retain(|_, s| s.parent != child);
+        swallowed.0.remove(&child)
+    };
+    let Some(Swallowed { parent, floating }) = swallowed else {
+        return Ok(());
+    };
+
+    x.modify_and_refresh(state, |cs| {
+        if cs.contains(&child) {
+            replace_client(cs, child, parent);
+        } else {
+            let tag = cs.current_tag().to_string();
+            cs.move_client_to_tag(&parent, tag);
+        }
+        if let Some(r) = floating {
+            cs.float(parent, r)
+                .log_err("Couldn't float a restored window");
+        }
+    })
+}
+
+fn first_pid<X: XConn>(x: &X, client: Xid) -> Option<u32> {
+    client_pids(x, client)?.first().copied()
+}
+
+/// Put `new` where `old` is in its workspace, taking its focus if it had
+/// it, and leave `old` out of every workspace.
+fn replace_client(cs: &mut ClientSet, old: Xid, new: Xid) {
+    cs.remove_client(&new);
+    cs.sink(&old);
+
+    for ws in cs.workspaces_mut().filter(|ws| ws.contains(&old)) {
+        let swap = |&c: &Xid| if c == old { new } else { c };
+        let clients: Vec<Xid> = ws.clients().map(swap).collect();
+        let focus = ws.focus().map(swap);
+        rebuild_workspace(ws, clients, focus);
+    }
+}
+
+fn rebuild_workspace(ws: &mut Workspace<Xid>, clients: Vec<Xid>, focus: Option<Xid>) {
+    let mut stack = Stack::try_from_iter(clients);
+    if let (Some(stack), Some(focus)) = (&mut stack, focus) {
+        stack.focus_element(&focus);
+    }
+
+    let layouts = ws.set_available_layouts(LayoutStack::default());
+    *ws = Workspace::new(ws.id(), ws.tag(), layouts, stack);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeConn;
+
+    const TERMINAL: u32 = 1;
+    const EDITOR: u32 = 2;
+    const VIEWER: u32 = 3;
+
+    fn conn() -> FakeConn {
+        FakeConn::new(vec![Rect::new(0, 0, 1000, 800)])
+    }
+
+    /// A terminal and editor on "1", with a viewer started from the
+    /// terminal swallowing it. Clients are inserted above the focus, so the
+    /// viewer starts at the bottom of the stack and the terminal at the top.
+    fn swallowed(x: &FakeConn) -> State<FakeConn> {
+        let mut state = x.state(&["1", "2"]);
+        state
+            .client_set
+            .add_invisible_workspace(SWALLOWED_TAG)
+            .unwrap();
+        for id in [VIEWER, EDITOR, TERMINAL] {
+            state.client_set.insert(Xid::from(id));
+        }
+        hide_parent(&mut state, Xid::from(TERMINAL), Xid::from(VIEWER), x);
+
+        state
+    }
+
+    fn clients(state: &State<FakeConn>, tag: &str) -> Vec<u32> {
+        let ws = state.client_set.workspace(tag).unwrap();
+        ws.clients().map(|&c| *c).collect()
+    }
+
+    fn focus(state: &State<FakeConn>, tag: &str) -> Option<u32> {
+        let ws = state.client_set.workspace(tag).unwrap();
+        ws.focus().map(|&c| *c)
+    }
+
+    #[test]
+    fn swallowed_parent_stays_managed_on_the_hidden_workspace() {
+        let state = swallowed(&conn());
+
+        assert_eq!(clients(&state, "1"), vec![VIEWER, EDITOR]);
+        assert_eq!(focus(&state, "1"), Some(VIEWER));
+        assert_eq!(clients(&state, SWALLOWED_TAG), vec![TERMINAL]);
+        assert!(state
+            .client_set
+            .ordered_tags()
+            .iter()
+            .all(|t| t != SWALLOWED_TAG));
+    }
+
+    #[test]
+    fn parent_is_restored_in_place_of_its_child() {
+        let x = conn();
+        let mut state = swallowed(&x);
+
+        restore_parent(Xid::from(VIEWER), &mut state, &x).unwrap();
+
+        assert_eq!(clients(&state, "1"), vec![TERMINAL, EDITOR]);
+        assert_eq!(focus(&state, "1"), Some(TERMINAL));
+        assert!(clients(&state, SWALLOWED_TAG).is_empty());
+    }
+
+    #[test]
+    fn parent_is_restored_to_the_current_workspace_if_its_child_is_gone() {
+        let x = conn();
+        let mut state = swallowed(&x);
+        state.client_set.remove_client(&Xid::from(VIEWER));
+        state.client_set.focus_tag("2");
+
+        restore_parent(Xid::from(VIEWER), &mut state, &x).unwrap();
+
+        assert_eq!(clients(&state, "1"), vec![EDITOR]);
+        assert_eq!(clients(&state, "2"), vec![TERMINAL]);
+    }
+
+    #[test]
+    fn closed_parent_is_not_restored() {
+        let x = conn();
+        let mut state = swallowed(&x);
+        state.client_set.remove_client(&Xid::from(TERMINAL));
+
+        restore_parent(Xid::from(TERMINAL), &mut state, &x).unwrap();
+        restore_parent(Xid::from(VIEWER), &mut state, &x).unwrap();
+
+        assert_eq!(clients(&state, "1"), vec![VIEWER, EDITOR]);
+        assert!(!state.client_set.contains(&Xid::from(TERMINAL)));
+    }
+
+    #[test]
+    fn floating_parent_is_floated_again_when_restored() {
+        let mut x = conn();
+        let r = Rect::new(100, 100, 300, 200);
+        x.geometry.insert(Xid::from(TERMINAL), r);
+        let mut state = x.state(&["1"]);
+        state
+            .client_set
+            .add_invisible_workspace(SWALLOWED_TAG)
+            .unwrap();
+        for id in [TERMINAL, VIEWER] {
+            state.client_set.insert(Xid::from(id));
+        }
+        state.client_set.float(Xid::from(TERMINAL), r).unwrap();
+
+        hide_parent(&mut state, Xid::from(TERMINAL), Xid::from(VIEWER), &x);
+        assert!(state.client_set.is_floating(&Xid::from(VIEWER)));
+
+        restore_parent(Xid::from(VIEWER), &mut state, &x).unwrap();
+        assert!(state.client_set.is_floating(&Xid::from(TERMINAL)));
+    }
+}
@@ -0,0 +1,212 @@
+use penrose::{
+    core::{
+        bindings::{KeyCode, MouseState},
+        Config, State, WindowManager,
+    },
+    pure::geometry::{Point, Rect},
+    x::{
+        event::ClientMessage,
+        property::{Prop, WindowAttributes, WmState},
+        ClientAttr, ClientConfig, XConn, XEvent,
+    },
+    Result, Xid,
+};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A directory under the system temp dir that is removed when dropped, for
+/// standing in for `/proc`, `/sys` or `$PATH`.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let n = COUNT.fetch_add(1, Ordering::Relaxed);
+        let dir = env::temp_dir().join(format!("penrose-contrib-test-{}-{n}", process::id()));
+        fs::create_dir_all(&dir).expect("a temporary directory");
+
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write `contents` to `path` under this directory, creating any missing
+    /// parents.
+    pub fn write(&self, path: impl AsRef<Path>, contents: &str) -> PathBuf {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().expect("a parent directory"))
+            .expect("the parent directories");
+        fs::write(&path, contents).expect("a writable file");
+
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A fake X server holding fixed properties for each client, in the manner of
+/// penrose's `MockXConn` which is only available to its own tests. Anything
+/// that would talk to a real server is a no-op.
+#[derive(Debug, Clone, Default)]
+pub struct FakeConn {
+    pub screens: Vec<Rect>,
+    pub props: HashMap<Xid, HashMap<String, Prop>>,
+    pub geometry: HashMap<Xid, Rect>,
+}
+
+impl FakeConn {
+    pub fn new(screens: Vec<Rect>) -> Self {
+        Self {
+            screens,
+            ..Default::default()
+        }
+    }
+
+    /// Give `client` the `WM_CLASS`, title and `_NET_WM_PID` of a window.
+    pub fn with_client(mut self, client: u32, classes: &[&str], title: &str, pid: u32) -> Self {
+        let props = self.props.entry(Xid::from(client)).or_default();
+        let strings =
+            |strs: &[&str]| Prop::UTF8String(strs.iter().map(|s| s.to_string()).collect());
+        props.insert("WM_CLASS".to_string(), strings(classes));
+        props.insert("_NET_WM_NAME".to_string(), strings(&[title]));
+        props.insert("_NET_WM_PID".to_string(), Prop::Cardinal(vec![pid]));
+
+        self
+    }
+
+    /// A window manager state with these tags over the fake screens.
+    pub fn state(&self, tags: &[&str]) -> State<FakeConn> {
+        let config = Config {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..Default::default()
+        };
+        let wm = WindowManager::new(config, HashMap::new(), HashMap::new(), self.clone())
+            .expect("a window manager over the fake screens");
+
+        wm.state
+    }
+}
+
+impl XConn for FakeConn {
+    fn root(&self) -> Xid {
+        Xid::from(0)
+    }
+
+    fn screen_details(&self) -> Result<Vec<Rect>> {
+        Ok(self.screens.clone())
+    }
+
+    fn cursor_position(&self) -> Result<Point> {
+        Ok(Point::default())
+    }
+
+    fn grab(&self, _: &[KeyCode], _: &[MouseState]) -> Result<()> {
+        Ok(())
+    }
+
+    fn next_event(&self) -> Result<XEvent> {
+        unimplemented!("the fake X server has no events")
+    }
+
+    fn flush(&self) {}
+
+    fn intern_atom(&self, _: &str) -> Result<Xid> {
+        Ok(Xid::from(0))
+    }
+
+    fn atom_name(&self, _: Xid) -> Result<String> {
+        Ok(String::new())
+    }
+
+    fn client_geometry(&self, client: Xid) -> Result<Rect> {
+        Ok(self
+            .geometry
+            .get(&client)
+            .copied()
+            .unwrap_or(Rect::new(0, 0, 400, 300)))
+    }
+
+    fn existing_clients(&self) -> Result<Vec<Xid>> {
+        Ok(self.props.keys().copied().collect())
+    }
+
+    fn map(&self, _: Xid) -> Result<()> {
+        Ok(())
+    }
+
+    fn unmap(&self, _: Xid) -> Result<()> {
+        Ok(())
+    }
+
+    fn kill(&self, _: Xid) -> Result<()> {
+        Ok(())
+    }
+
+    fn focus(&self, _: Xid) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_prop(&self, client: Xid, prop_name: &str) -> Result<Option<Prop>> {
+        Ok(self
+            .props
+            .get(&client)
+            .and_then(|props| props.get(prop_name))
+            .cloned())
+    }
+
+    fn list_props(&self, client: Xid) -> Result<Vec<String>> {
+        Ok(self
+            .props
+            .get(&client)
+            .map(|props| props.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn get_wm_state(&self, _: Xid) -> Result<Option<WmState>> {
+        Ok(None)
+    }
+
+    fn get_window_attributes(&self, _: Xid) -> Result<WindowAttributes> {
+        unimplemented!("the fake X server has no window attributes")
+    }
+
+    fn set_wm_state(&self, _: Xid, _: WmState) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_prop(&self, _: Xid, _: &str, _: Prop) -> Result<()> {
+        Ok(())
+    }
+
+    fn delete_prop(&self, _: Xid, _: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_client_attributes(&self, _: Xid, _: &[ClientAttr]) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_client_config(&self, _: Xid, _: &[ClientConfig]) -> Result<()> {
+        Ok(())
+    }
+
+    fn send_client_message(&self, _: ClientMessage) -> Result<()> {
+        Ok(())
+    }
+
+    fn warp_pointer(&self, _: Xid, _: i16, _: i16) -> Result<()> {
+        Ok(())
+    }
+}
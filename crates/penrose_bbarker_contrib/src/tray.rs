@@ -0,0 +1,362 @@
+use penrose::{Error, Result};
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{
+            Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConfigureWindowAux,
+            ConnectionExt as _, CreateWindowAux, EventMask, PropMode, SetMode, StackMode, Window,
+            WindowClass,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+    COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, CURRENT_TIME, NONE,
+};
+
+use crate::log::log_penrose;
+
+// Opcodes from the system tray and XEmbed specs
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+const SYSTEM_TRAY_ORIENTATION_HORZ: u32 = 0;
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+const XEMBED_VERSION: u32 = 0;
+
+/// The client message type sent to the root window when icons are docked or
+/// removed, so that the window manager wakes up and redraws its bar around
+/// the tray.
+pub const TRAY_CHANGED_ATOM: &str = "_PENROSE_BBARKER_TRAY_CHANGED";
+
+/// Where the tray should be shown: icons are laid out in a row ending at
+/// `right`, each `icon_size` pixels square.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrayAnchor {
+    pub right: i32,
+    pub y: i32,
+    pub icon_size: u16,
+    /// Background color shown behind the icons, as 0xRRGGBB
+    pub bg: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Atoms {
+    selection: Atom,
+    opcode: Atom,
+    orientation: Atom,
+    manager: Atom,
+    xembed: Atom,
+    changed: Atom,
+}
+
+impl Atoms {
+    fn new(conn: &RustConnection, screen_num: usize) -> Result<Self> {
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+
+        Ok(Self {
+            selection: atom(&format!("_NET_SYSTEM_TRAY_S{screen_num}"))?,
+            opcode: atom("_NET_SYSTEM_TRAY_OPCODE")?,
+            orientation: atom("_NET_SYSTEM_TRAY_ORIENTATION")?,
+            manager: atom("MANAGER")?,
+            xembed: atom("_XEMBED")?,
+            changed: atom(TRAY_CHANGED_ATOM)?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Icons {
+    windows: Vec<Window>,
+    anchor: Option<TrayAnchor>,
+}
+
+/// An XEmbed system tray: the owner of `_NET_SYSTEM_TRAY_S<n>` that programs
+/// dock their status icons with.
+///
+/// The tray has its own X connection and a background thread that docks and
+/// removes icons as they come and go, so it lives on while the bar around it
+/// is rebuilt. The bar only needs to leave room for [`icon_count`] icons and
+/// say where they go with [`place`].
+///
+/// [`icon_count`]: SystemTray::icon_count
+/// [`place`]: SystemTray::place
+#[derive(Debug)]
+pub struct SystemTray {
+    conn: Arc<RustConnection>,
+    window: Window,
+    icons: Arc<Mutex<Icons>>,
+}
+
+impl SystemTray {
+    /// Take ownership of the system tray selection and start docking icons.
+    /// This fails if another system tray is already running.
+    pub fn start() -> Result<Self> {
+        let (conn, screen_num) = RustConnection::connect(None)?;
+        let atoms = Atoms::new(&conn, screen_num)?;
+        let screen = &conn.setup().roots[screen_num];
+        let root = screen.root;
+
+        if conn.get_selection_owner(atoms.selection)?.reply()?.owner != NONE {
+            return Err(Error::Custom(
+                "another system tray is already running".to_string(),
+            ));
+        }
+
+        // Override redirect keeps the window manager from managing the tray
+        // window: it's placed over the bar by `place` instead
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new()
+                .override_redirect(1)
+                .background_pixel(screen.black_pixel)
+                .event_mask(EventMask::SUBSTRUCTURE_NOTIFY),
+        )?;
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atoms.orientation,
+            AtomEnum::CARDINAL,
+            &[SYSTEM_TRAY_ORIENTATION_HORZ],
+        )?;
+
+        conn.set_selection_owner(window, atoms.selection, CURRENT_TIME)?;
+        if conn.get_selection_owner(atoms.selection)?.reply()?.owner != window {
+            return Err(Error::Custom(
+                "unable to take ownership of the system tray".to_string(),
+            ));
+        }
+
+        // Let programs that started before us know that they can dock now
+        let announce = ClientMessageEvent::new(
+            32,
+            root,
+            atoms.manager,
+            [CURRENT_TIME, atoms.selection, window, 0, 0],
+        );
+        conn.send_event(false, root, EventMask::STRUCTURE_NOTIFY, announce)?;
+        conn.flush()?;
+
+        let tray = Self {
+            conn: Arc::new(conn),
+            window,
+            icons: Arc::new(Mutex::new(Icons::default())),
+        };
+
+        let (conn, icons) = (tray.conn.clone(), tray.icons.clone());
+        thread::spawn(move || {
+            let msg = match handle_events(&conn, root, window, atoms, &icons) {
+                Ok(()) => "system tray: another tray took over".to_string(),
+                Err(e) => format!("system tray stopped: {e}"),
+            };
+            log_penrose(&msg).unwrap_or_else(|e| eprintln!("Couldn't log {msg}: {e:?}"));
+        });
+
+        Ok(tray)
+    }
+
+    pub fn icon_count(&self) -> usize {
+        lock(&self.icons).windows.len()
+    }
+
+    /// Move the tray to `anchor`, if it isn't there already.
+    pub fn place(&self, anchor: TrayAnchor) -> Result<()> {
+        let mut icons = lock(&self.icons);
+        if icons.anchor == Some(anchor) {
+            return Ok(());
+        }
+        icons.anchor = Some(anchor);
+
+        arrange(&self.conn, self.window, &icons)
+    }
+}
+
+fn lock(icons: &Mutex<Icons>) -> MutexGuard<'_, Icons> {
+    icons
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Dock and remove icons until the connection fails or another tray takes
+/// the selection from us.
+///
+/// Requests about icons that have already gone away come back as X errors
+/// rather than failing, and are ignored along with every other event.
+fn handle_events(
+    conn: &RustConnection,
+    root: Window,
+    window: Window,
+    atoms: Atoms,
+    icons: &Mutex<Icons>,
+) -> Result<()> {
+    loop {
+        let changed = match conn.wait_for_event()? {
+            Event::ClientMessage(e) if e.window == window && e.type_ == atoms.opcode => {
+                let data = e.data.as_data32();
+                data[1] == SYSTEM_TRAY_REQUEST_DOCK && dock(conn, window, atoms, icons, data[2])?
+            }
+            Event::DestroyNotify(e) => undock(conn, window, icons, e.window)?,
+            // Programs can take their icon back by reparenting it elsewhere
+            Event::ReparentNotify(e) if e.parent != window => {
+                undock(conn, window, icons, e.window)?
+            }
+            Event::SelectionClear(e) if e.selection == atoms.selection => {
+                return release(conn, root, window, icons);
+            }
+            _ => false,
+        };
+
+        if changed {
+            wake_window_manager(conn, root, atoms)?;
+        }
+    }
+}
+
+/// The bar only checks whether it needs redrawing as the window manager
+/// handles an event, and our icons don't give it one.
+fn wake_window_manager(conn: &RustConnection, root: Window, atoms: Atoms) -> Result<()> {
+    let msg = ClientMessageEvent::new(32, root, atoms.changed, [0u32; 5]);
+    conn.send_event(false, root, EventMask::SUBSTRUCTURE_NOTIFY, msg)?;
+    conn.flush()?;
+
+    Ok(())
+}
+
+/// Dock `icon`, returning whether it is new to the tray.
+fn dock(
+    conn: &RustConnection,
+    window: Window,
+    atoms: Atoms,
+    icons: &Mutex<Icons>,
+    icon: Window,
+) -> Result<bool> {
+    let mut icons = lock(icons);
+    if icon == NONE || icons.windows.contains(&icon) {
+        return Ok(false);
+    }
+
+    // Watch the icon so that we hear about it being destroyed at any point
+    // from here on, then skip it if it's gone already
+    conn.change_window_attributes(
+        icon,
+        &ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY),
+    )?;
+    if conn.get_window_attributes(icon)?.reply().is_err() {
+        return Ok(false);
+    }
+    icons.windows.push(icon);
+
+    // The save set hands icons back to the root window if we exit first
+    conn.change_save_set(SetMode::INSERT, icon)?;
+    conn.reparent_window(icon, window, 0, 0)?;
+    let notify = ClientMessageEvent::new(
+        32,
+        icon,
+        atoms.xembed,
+        [
+            CURRENT_TIME,
+            XEMBED_EMBEDDED_NOTIFY,
+            0,
+            window,
+            XEMBED_VERSION,
+        ],
+    );
+    conn.send_event(false, icon, EventMask::NO_EVENT, notify)?;
+    conn.map_window(icon)?;
+    arrange(conn, window, &icons)?;
+
+    Ok(true)
+}
+
+/// Remove `icon`, returning whether it was in the tray.
+fn undock(
+    conn: &RustConnection,
+    window: Window,
+    icons: &Mutex<Icons>,
+    icon: Window,
+) -> Result<bool> {
+    let mut icons = lock(icons);
+    let before = icons.windows.len();
+    icons.windows.retain(|&w| w != icon);
+    if icons.windows.len() == before {
+        return Ok(false);
+    }
+    arrange(conn, window, &icons)?;
+
+    Ok(true)
+}
+
+/// Hand every icon back to the root window for the next tray to dock.
+fn release(
+    conn: &RustConnection,
+    root: Window,
+    window: Window,
+    icons: &Mutex<Icons>,
+) -> Result<()> {
+    let mut icons = lock(icons);
+    for icon in icons.windows.drain(..) {
+        conn.unmap_window(icon)?;
+        conn.reparent_window(icon, root, 0, 0)?;
+    }
+    conn.destroy_window(window)?;
+    conn.flush()?;
+
+    Ok(())
+}
+
+/// Lay the icons out in a row at the anchor, hiding the tray while empty.
+fn arrange(conn: &RustConnection, window: Window, icons: &Icons) -> Result<()> {
+    let Some(anchor) = icons.anchor else {
+        return Ok(());
+    };
+    if icons.windows.is_empty() {
+        conn.unmap_window(window)?;
+        conn.flush()?;
+        return Ok(());
+    }
+
+    let size = anchor.icon_size as u32;
+    let width = size * icons.windows.len() as u32;
+    conn.change_window_attributes(
+        window,
+        &ChangeWindowAttributesAux::new().background_pixel(anchor.bg),
+    )?;
+    conn.configure_window(
+        window,
+        &ConfigureWindowAux::new()
+            .x(anchor.right - width as i32)
+            .y(anchor.y)
+            .width(width)
+            .height(size)
+            .stack_mode(StackMode::ABOVE),
+    )?;
+    for (ix, &icon) in icons.windows.iter().enumerate() {
+        conn.configure_window(
+            icon,
+            &ConfigureWindowAux::new()
+                .x((ix as u32 * size) as i32)
+                .y(0)
+                .width(size)
+                .height(size),
+        )?;
+    }
+    conn.map_window(window)?;
+    conn.clear_area(true, window, 0, 0, 0, 0)?;
+    conn.flush()?;
+
+    Ok(())
+}
@@ -1,23 +1,96 @@
 use penrose::{
     core::State,
     pure::Workspace,
-    x::{XConn, XConnExt},
+    x::{property::Prop, Atom, XConn, XConnExt},
     Xid,
 };
-use sysinfo::Pid;
+use std::path::{Path, PathBuf};
+use sysinfo::Process;
 
-pub struct TagAndAppInfo {
+use crate::process_cache::ProcessCache;
+
+/// A managed window along with what's known about the process behind it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub id: Xid,
+    pub title: String,
+    /// The first `WM_CLASS` string, empty if it hasn't set one
+    pub instance: String,
+    /// The second `WM_CLASS` string, empty if it hasn't set one
+    pub class: String,
+    /// `WM_WINDOW_ROLE`, which tells apart the windows of one program
+    pub role: Option<String>,
+    pub pid: Option<u32>,
+    /// The executable name, `None` without a pid or once the process is gone
+    pub exe: Option<String>,
+    pub cmdline: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub floating: bool,
+    /// Whether the client has asked for attention through `_NET_WM_STATE`
+    pub urgent: bool,
+}
+
+impl ClientInfo {
+    pub fn new<X: XConn>(processes: &ProcessCache, state: &State<X>, xcon: &X, id: Xid) -> Self {
+        let mut classes = client_classes(xcon, id).into_iter();
+        let pid = client_pids(xcon, id).and_then(|pids| pids.first().copied());
+        let (exe, cmdline, cwd) = pid
+            .and_then(|pid| {
+                processes.with_process(pid, |process| {
+                    (
+                        Some(exe_name(process)),
+                        process
+                            .cmd()
+                            .iter()
+                            .map(|arg| arg.to_string_lossy().into_owned())
+                            .collect(),
+                        process.cwd().map(Path::to_path_buf),
+                    )
+                })
+            })
+            .unwrap_or_default();
+
+        Self {
+            id,
+            title: xcon.window_title(id).unwrap_or_default(),
+            instance: classes.next().unwrap_or_default(),
+            class: classes.next().unwrap_or_default(),
+            role: match xcon.get_prop(id, "WM_WINDOW_ROLE") {
+                Ok(Some(Prop::UTF8String(strs))) => strs.into_iter().next(),
+                _ => None,
+            },
+            pid,
+            exe,
+            cmdline,
+            cwd,
+            floating: state.client_set.is_floating(&id),
+            urgent: matches!(
+                xcon.get_prop(id, Atom::NetWmState.as_ref()),
+                Ok(Some(Prop::Atom(atoms)))
+                    if atoms.iter().any(|a| a == Atom::NetWmStateDemandsAttention.as_ref())
+            ),
+        }
+    }
+
+    /// Whether `class` is either of the client's `WM_CLASS` strings.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.instance == class || self.class == class
+    }
+}
+
+/// The tag of a workspace and its clients, in stack order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceClients {
     pub tag: String,
-    pub titles: Vec<String>,
-    pub processes: Vec<String>,
+    pub clients: Vec<ClientInfo>,
 }
 
-pub fn workspace_app_info<X>(
-    system: &sysinfo::System,
+pub fn workspace_clients<X>(
+    processes: &ProcessCache,
     state: &State<X>,
     xcon: &X,
     ws: &Workspace<Xid>,
-) -> TagAndAppInfo
+) -> WorkspaceClients
 where
     X: XConn,
 {
@@ -25,37 +98,42 @@ where
         .client_set
         .tag_for_workspace_id(ws.id())
         .unwrap_or_default();
-    let titles = ws
-        .clients()
-        .map(|client| xcon.window_title(*client).unwrap_or_default())
-        .collect::<Vec<String>>();
-    let processes = ws
+    let clients = ws
         .clients()
-        .map(|client| xcon.get_prop(*client, "_NET_WM_PID"))
-        .map(|prop_res| match prop_res {
-            Ok(Some(penrose::x::Prop::Cardinal(cardinals))) => cardinals
-                .into_iter()
-                .map(|pid| {
-                    if let Some(process) = system.process(Pid::from(pid as usize)) {
-                        process
-                            .exe()
-                            .and_then(|exe_path| exe_path.file_name())
-                            .map_or_else(
-                                || "Unknown".to_string(),
-                                |os_str| os_str.to_string_lossy().into_owned(),
-                            )
-                    } else {
-                        String::new()
-                    }
-                })
-                .collect::<Vec<String>>()
-                .join(","),
-            _ => String::new(),
-        })
-        .collect::<Vec<String>>();
-    TagAndAppInfo {
-        tag,
-        titles,
-        processes,
+        .map(|&id| ClientInfo::new(processes, state, xcon, id))
+        .collect();
+
+    WorkspaceClients { tag, clients }
+}
+
+/// The `_NET_WM_PID` of a client, if it has set one.
+pub fn client_pids<X: XConn>(xcon: &X, client: Xid) -> Option<Vec<u32>> {
+    match xcon.get_prop(client, "_NET_WM_PID") {
+        Ok(Some(Prop::Cardinal(cardinals))) => Some(cardinals),
+        _ => None,
+    }
+}
+
+/// The `WM_CLASS` strings of a client, empty if it hasn't set any.
+pub fn client_classes<X: XConn>(xcon: &X, client: Xid) -> Vec<String> {
+    match xcon.get_prop(client, Atom::WmClass.as_ref()) {
+        Ok(Some(Prop::UTF8String(classes))) => classes,
+        _ => Vec::new(),
     }
 }
+
+/// The executable name of a running process: `None` if there is no such
+/// process and "Unknown" if its executable can't be read.
+pub fn process_name(processes: &ProcessCache, pid: u32) -> Option<String> {
+    processes.with_process(pid, exe_name)
+}
+
+fn exe_name(process: &Process) -> String {
+    process
+        .exe()
+        .and_then(|exe_path| exe_path.file_name())
+        .map_or_else(
+            || "Unknown".to_string(),
+            |os_str| os_str.to_string_lossy().into_owned(),
+        )
+}
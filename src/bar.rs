@@ -1,5 +1,9 @@
-use crate::{BLACK, BLUE, FONT, GREY, WHITE};
-use do_notation::m;
+use crate::{
+    config::{BarWidget, UserConfig},
+    icons::IconConfig,
+    screens::BarScreens,
+    theme::Theme,
+};
 use penrose::{
     core::State,
     pure::geometry::{Point, Rect},
@@ -8,133 +12,66 @@ use penrose::{
 };
 use penrose_bbarker_contrib::{
     log::LogPenroseError,
-    workspaces::{workspace_app_info, TagAndAppInfo},
+    status::{
+        battery::Battery,
+        system::{percent, system_snapshot},
+        volume::Volume,
+        wifi::Wifi,
+        Level,
+    },
+    tray::{SystemTray, TrayAnchor},
+    workspaces::{workspace_clients, WorkspaceClients},
     SYSTEM,
 };
 use penrose_ui::{
     bar::{
         widgets::{
-            sys::{
-                helpers::battery_file_search,
-                interval::{amixer_volume, battery_summary, current_date_and_time, wifi_network},
-            },
-            ActiveWindowName, CurrentLayout, FocusState, Widget, WorkspacesUi, WorkspacesWidget,
-            WsMeta,
+            sys::helpers::date_text, ActiveWindowName, CurrentLayout, FocusState, Text, Widget,
+            WorkspacesUi, WorkspacesWidget, WsMeta,
         },
         PerScreen, Position, StatusBar,
     },
     Context, Result, TextStyle,
 };
-use std::time::Duration;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
 
 use once_cell::sync::Lazy;
 
-pub static BATTERY: Lazy<String> =
-    Lazy::new(|| battery_file_search().unwrap_or("BAT1".to_string()));
-// TODO: Work out how to change the highlight color to RED when we pass a certain
-//   date '+%H%M' might be a quick and dirty way to get the current hour/minute value for
-//   comparison against a switch time for changing out the color of theactive window widget at
-//   least?
-
 pub const MAX_ACTIVE_WINDOW_CHARS: usize = 50;
 pub const BAR_HEIGHT_PX_PRIMARY: u32 = 24;
 pub const BAR_HEIGHT_PX_EXTERNAL: u32 = 18;
 pub const BAR_POINT_SIZE_PRIMARY: u8 = 12;
 pub const BAR_POINT_SIZE_EXTERNAL: u8 = 8;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct AppInfo {
-    titles: Vec<String>,
-    processes: Vec<String>,
-}
-
-// TODO: have this be a bit more customizable by
-//     : taking some kind of mapping or config struct
-struct AppConfig {
-    titles: Vec<(String, String)>,
-    processes: Vec<(String, String)>,
-}
-
-impl Default for AppConfig {
-    fn default() -> Self {
-        AppConfig {
-            titles: [("LibreOffice Calc", "󰧷"), ("LibreOffice Writer", "")]
-                .iter()
-                .map(|(key, val)| (key.to_string(), val.to_string()))
-                .collect(),
-            processes: [("spotify", "🎵"), ("firefox", "🦊")]
-                .iter()
-                .map(|(key, val)| (key.to_string(), val.to_string()))
-                .collect(),
-        }
-    }
-}
-
-static APP_CONFIG: Lazy<AppConfig> = Lazy::new(AppConfig::default);
-
-impl AppInfo {
-    fn iconic_tag(&self, tag: String) -> String {
-        // let icon_list = vec![
-        //     if self.processes.iter().any(|pname| pname.contains("spotify")) {
-        //         "🎵"
-        //     } else {
-        //         ""
-        //     },
-        //     // \udb81\udd9f
-        // ];
-        let icon_list: Vec<String> = APP_CONFIG
-            .processes
-            .iter()
-            .map(|(proc, icon)| {
-                if self.processes.iter().any(|pname| pname.contains(proc)) {
-                    icon
-                } else {
-                    ""
-                }
-            })
-            .chain(APP_CONFIG.titles.iter().map(|(conf_title, icon)| {
-                if self.titles.iter().any(|title| title.contains(conf_title)) {
-                    icon
-                } else {
-                    ""
-                }
-            }))
-            .map(|str| str.to_string())
-            .collect();
-        let icons: String = icon_list.concat();
-        if icons.is_empty() {
-            tag
-        } else {
-            format!("{tag}{icons}")
-        }
-    }
-}
-
-impl From<TagAndAppInfo> for AppInfo {
-    fn from(tag_app_info: TagAndAppInfo) -> Self {
-        Self {
-            titles: tag_app_info.titles,
-            processes: tag_app_info.processes,
-        }
-    }
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub struct MyWorkspaceUi {
     fg_1: Color,
     fg_2: Color,
     bg_1: Color,
     bg_2: Color,
-    ws_apps: Vec<AppInfo>,
+    icons: IconConfig,
+    ws_apps: Vec<WorkspaceClients>,
 }
 
 impl MyWorkspaceUi {
-    fn new(style: TextStyle, highlight: impl Into<Color>, empty_fg: impl Into<Color>) -> Self {
+    fn new(
+        style: TextStyle,
+        highlight: impl Into<Color>,
+        empty_fg: impl Into<Color>,
+        icons: IconConfig,
+    ) -> Self {
         Self {
             fg_1: style.fg,
             fg_2: empty_fg.into(),
             bg_1: highlight.into(),
             bg_2: style.bg.unwrap_or_else(|| 0x000000.into()),
+            icons,
             ws_apps: Vec::new(),
         }
     }
@@ -154,7 +91,7 @@ impl WorkspacesUi for MyWorkspaceUi {
         let new_ws_apps = state
             .client_set
             .ordered_workspaces()
-            .map(|ws| workspace_app_info(&SYSTEM, state, xcon, ws).into())
+            .map(|ws| workspace_clients(&SYSTEM, state, xcon, ws))
             .collect();
         if self.ws_apps == new_ws_apps {
             false
@@ -188,19 +125,14 @@ impl WorkspacesUi for MyWorkspaceUi {
 
     fn ui_tag(&self, workspace_meta: &WsMeta) -> String {
         match workspace_meta.occupied() {
-            true => {
-                let tag_string = workspace_meta.tag().to_string();
-                match m! {
-                    tag_num <- tag_string.parse::<usize>()
-                      .log_err(&format!("couldn't parse int from {tag_string}"));
-                    ws_ix <- tag_num.checked_sub(1)
-                      .log_err(&format!("In ui_tag: couldn't subtract 1 from {tag_num}"));
-                    self.ws_apps.get(ws_ix)
-                } {
-                    Some(app_info) => app_info.iconic_tag(tag_string),
-                    None => tag_string,
-                }
-            }
+            true => self
+                .ws_apps
+                .iter()
+                .find(|ws| ws.tag == workspace_meta.tag())
+                .map_or_else(
+                    || workspace_meta.tag().to_string(),
+                    |ws| format!("{}{}", ws.tag, self.icons.icons(&ws.clients)),
+                ),
             false => String::new(),
         }
     }
@@ -212,18 +144,26 @@ fn new_workspaces(
     style: TextStyle,
     highlight: impl Into<Color>,
     empty_fg: impl Into<Color>,
+    icons: IconConfig,
 ) -> MyWorkspaces {
-    let ui = MyWorkspaceUi::new(style, highlight, empty_fg);
+    let ui = MyWorkspaceUi::new(style, highlight, empty_fg, icons);
 
     WorkspacesWidget::new_with_ui(ui)
 }
 
-fn base_widgets<X: XConn>() -> Vec<Box<dyn Widget<X>>> {
-    let highlight: Color = BLUE.into();
-    let empty_ws: Color = GREY.into();
+fn base_widgets<X: XConn>(config: &UserConfig, theme: Theme) -> Vec<Box<dyn Widget<X>>> {
+    let Theme {
+        bg,
+        fg,
+        highlight,
+        inactive,
+        urgent,
+        warning,
+        ..
+    } = theme;
     let style = TextStyle {
-        fg: WHITE.into(),
-        bg: Some(BLACK.into()),
+        fg,
+        bg: Some(bg),
         padding: (2, 2),
     };
 
@@ -231,51 +171,399 @@ fn base_widgets<X: XConn>() -> Vec<Box<dyn Widget<X>>> {
         padding: (5, 5),
         ..style
     };
+    // Indexed by `Level`
+    let lstyles = [
+        pstyle,
+        TextStyle {
+            fg: warning,
+            ..pstyle
+        },
+        TextStyle {
+            fg: urgent,
+            ..pstyle
+        },
+    ];
 
     let ms = |n: u64| Duration::from_millis(n);
 
-    vec![
-        Box::new(Wedge::start(BLUE, BLACK)),
-        Box::new(new_workspaces(style, highlight, empty_ws)),
-        Box::new(CurrentLayout::new(style)),
-        Box::new(Wedge::end(BLUE, BLACK).only_with_focus()),
-        Box::new(ActiveWindowName::new(
-            MAX_ACTIVE_WINDOW_CHARS,
-            TextStyle {
-                bg: Some(highlight),
-                padding: (6, 4),
-                ..style
-            },
-            true,
-            false,
-        )),
-        Box::new(Wedge::start(BLUE, BLACK).only_with_focus()),
-        // The wttr.in API is freaking out a bit recently and hanging / returning errors
-        // so dropping this for now.
-        // Box::new(IntervalText::new(pstyle, weather_text, ms(300_000))),
-        Box::new(wifi_network(pstyle, ms(10_000))),
-        Box::new(battery_summary(&BATTERY, pstyle, ms(60_000))),
-        Box::new(amixer_volume("Master", pstyle, ms(1000))),
-        Box::new(current_date_and_time(pstyle, ms(10_000))),
-    ]
+    config
+        .bar
+        .widgets
+        .iter()
+        .flat_map(|widget| -> Vec<Box<dyn Widget<X>>> {
+            match widget {
+                BarWidget::Workspaces => vec![
+                    Box::new(Wedge::start(highlight, bg)),
+                    Box::new(new_workspaces(
+                        style,
+                        highlight,
+                        inactive,
+                        config.icons.clone(),
+                    )),
+                ],
+                BarWidget::CurrentLayout => vec![Box::new(CurrentLayout::new(style))],
+                BarWidget::ActiveWindow => vec![
+                    Box::new(Wedge::end(highlight, bg).only_with_focus()),
+                    Box::new(ActiveWindowName::new(
+                        config.bar.max_active_window_chars,
+                        TextStyle {
+                            bg: Some(highlight),
+                            padding: (6, 4),
+                            ..style
+                        },
+                        true,
+                        false,
+                    )),
+                    Box::new(Wedge::start(highlight, bg).only_with_focus()),
+                ],
+                // The wttr.in API is freaking out a bit recently and hanging / returning errors
+                // so dropping this for now.
+                // Box::new(Polled::new(pstyle, weather_text, ms(300_000))),
+                BarWidget::Wifi => {
+                    let (warning, critical) = (config.bar.wifi_warning, config.bar.wifi_critical);
+                    vec![Box::new(Polled::with_levels(
+                        lstyles,
+                        move || wifi_reading(warning, critical),
+                        ms(10_000),
+                    ))]
+                }
+                BarWidget::Battery => {
+                    let (warning, critical) =
+                        (config.bar.battery_warning, config.bar.battery_critical);
+                    vec![Box::new(Polled::with_levels(
+                        lstyles,
+                        move || battery_reading(warning, critical),
+                        ms(30_000),
+                    ))]
+                }
+                BarWidget::Volume => {
+                    let (card, control) =
+                        (config.bar.volume_card, config.bar.volume_control.clone());
+                    vec![Box::new(Polled::with_levels(
+                        lstyles,
+                        move || volume_reading(card, &control),
+                        ms(1000),
+                    ))]
+                }
+                BarWidget::DateTime => vec![Box::new(Polled::new(pstyle, date_text, ms(10_000)))],
+                BarWidget::Cpu => vec![Box::new(Polled::new(pstyle, cpu_text, ms(2000)))],
+                BarWidget::CpuCores => {
+                    vec![Box::new(Polled::new(pstyle, cpu_cores_text, ms(2000)))]
+                }
+                BarWidget::Memory => vec![Box::new(Polled::new(pstyle, memory_text, ms(2000)))],
+                BarWidget::LoadAverage => {
+                    vec![Box::new(Polled::new(pstyle, load_text, ms(5000)))]
+                }
+                BarWidget::Disk => config
+                    .bar
+                    .disk_mounts
+                    .iter()
+                    .map(|mount| -> Box<dyn Widget<X>> {
+                        let (mount, warning, critical) = (
+                            PathBuf::from(mount),
+                            config.bar.disk_warning,
+                            config.bar.disk_critical,
+                        );
+                        Box::new(Polled::with_levels(
+                            lstyles,
+                            move || disk_reading(&mount, warning, critical),
+                            ms(30_000),
+                        ))
+                    })
+                    .collect(),
+                BarWidget::Temperature => {
+                    let (sensor, warning, critical) = (
+                        config.bar.temperature_sensor.clone(),
+                        config.bar.temperature_warning,
+                        config.bar.temperature_critical,
+                    );
+                    vec![Box::new(Polled::with_levels(
+                        lstyles,
+                        move || temperature_reading(sensor.as_deref(), warning, critical),
+                        ms(2000),
+                    ))]
+                }
+            }
+        })
+        .collect()
+}
+
+fn battery_reading(warning: u8, critical: u8) -> Option<(String, Level)> {
+    let battery = Battery::read(Path::new("/"))?;
+    let level = if battery.is_charging() {
+        Level::Normal
+    } else {
+        Level::when_low(battery.percent, warning, critical)
+    };
+
+    Some((format!("{} {}%", battery.icon(), battery.percent), level))
+}
+
+/// Nothing at all on machines without wifi, rather than showing them as
+/// disconnected.
+fn wifi_reading(warning: u8, critical: u8) -> Option<(String, Level)> {
+    let root = Path::new("/");
+    match Wifi::read(root) {
+        Some(wifi) => {
+            let text = match wifi.ssid() {
+                Some(ssid) => format!("\u{f05a9} {ssid} {}%", wifi.quality),
+                None => format!("\u{f05a9} {}%", wifi.quality),
+            };
+            Some((text, Level::when_low(wifi.quality, warning, critical)))
+        }
+        None if Wifi::has_interface(root) => Some(("\u{f05aa}".to_string(), Level::Warning)),
+        None => None,
+    }
+}
+
+fn volume_reading(card: u32, control: &str) -> Option<(String, Level)> {
+    let volume = Volume::read(card, control).ok()?;
+    let level = if volume.muted {
+        Level::Warning
+    } else {
+        Level::Normal
+    };
+
+    Some((format!("{} {}%", volume.icon(), volume.percent), level))
+}
+
+fn cpu_text() -> Option<String> {
+    Some(format!("\u{f4bc} {:.0}%", system_snapshot().cpu))
+}
+
+fn cpu_cores_text() -> Option<String> {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let cores: String = system_snapshot()
+        .cores
+        .iter()
+        .map(|&usage| BARS[((usage / 100.0 * 8.0) as usize).min(7)])
+        .collect();
+
+    Some(format!("\u{f4bc} {cores}"))
+}
+
+fn memory_text() -> Option<String> {
+    let s = system_snapshot();
+    let memory = percent(s.memory_used, s.memory_total);
+
+    Some(if s.swap_used > 0 {
+        let swap = percent(s.swap_used, s.swap_total);
+        format!("\u{f035b} {memory}% swap {swap}%")
+    } else {
+        format!("\u{f035b} {memory}%")
+    })
+}
+
+fn load_text() -> Option<String> {
+    let [one, five, fifteen] = system_snapshot().load;
+
+    Some(format!("\u{f029a} {one:.2} {five:.2} {fifteen:.2}"))
+}
+
+fn disk_reading(mount: &Path, warning: u8, critical: u8) -> Option<(String, Level)> {
+    let disk = system_snapshot()
+        .disks
+        .into_iter()
+        .find(|disk| disk.mount_point == mount)?;
+    let used = percent(disk.used, disk.total);
+
+    Some((
+        format!("\u{f02ca} {} {used}%", mount.display()),
+        Level::when_high(used, warning, critical),
+    ))
+}
+
+fn temperature_reading(sensor: Option<&str>, warning: u8, critical: u8) -> Option<(String, Level)> {
+    let celsius = system_snapshot()
+        .temperatures
+        .into_iter()
+        .filter(|t| sensor.is_none_or(|sensor| t.label.contains(sensor)))
+        .map(|t| t.celsius)
+        .reduce(f32::max)?;
+
+    Some((
+        format!("\u{f2c9} {celsius:.0}°C"),
+        Level::when_high(celsius, warning as f32, critical as f32),
+    ))
 }
 
-pub fn status_bar<X: XConn>() -> Result<StatusBar<X>> {
-    let mut primary = base_widgets();
-    primary.push(Box::new(Spacer::new(0.07))); // reserve space for trayer
-    let external = base_widgets();
-
-    let bar = StatusBar::try_new_per_screen(
-        Position::Top,
-        BLACK,
-        FONT,
-        vec![
-            PerScreen::new(BAR_POINT_SIZE_PRIMARY, BAR_HEIGHT_PX_PRIMARY, primary),
-            PerScreen::new(BAR_POINT_SIZE_EXTERNAL, BAR_HEIGHT_PX_EXTERNAL, external),
-        ],
-    )?;
-
-    Ok(bar)
+/// The status bar described by `config`, drawn using `theme`, with a set of
+/// widgets for each of `screens`. The primary screen gets the full size bar,
+/// every other screen gets the compact one, and the system tray goes on the
+/// end of the bar on `config.bar.tray_screen`.
+pub fn status_bar<X: XConn>(
+    config: &UserConfig,
+    theme: Theme,
+    screens: &BarScreens,
+) -> Result<StatusBar<X>> {
+    let tray_screen = config
+        .bar
+        .tray_screen
+        .filter(|&ix| ix < screens.rects.len())
+        .unwrap_or(screens.primary);
+
+    // With no screens there is nothing to draw on until one turns up, but the
+    // bar still needs at least one set of widgets
+    let per_screen = if screens.rects.is_empty() {
+        vec![PerScreen::new(
+            config.bar.point_size_primary,
+            config.bar.height_primary,
+            base_widgets(config, theme),
+        )]
+    } else {
+        screens
+            .rects
+            .iter()
+            .enumerate()
+            .map(|(ix, &r)| {
+                let mut widgets = base_widgets(config, theme);
+                if ix == tray_screen {
+                    widgets.push(Box::new(Tray::new(r, theme.bg)));
+                }
+                if ix == screens.primary {
+                    PerScreen::new(
+                        config.bar.point_size_primary,
+                        config.bar.height_primary,
+                        widgets,
+                    )
+                } else {
+                    PerScreen::new(
+                        config.bar.point_size_external,
+                        config.bar.height_external,
+                        widgets,
+                    )
+                }
+            })
+            .collect()
+    };
+
+    StatusBar::try_new_per_screen(Position::Top, theme.bg, &config.bar.font, per_screen)
+}
+
+/// Text that is updated on an interval by a background thread.
+///
+/// This is penrose_ui's `IntervalText` without the shared update schedule:
+/// each widget owns its thread, which exits once the widget has been dropped.
+/// That lets us throw away and rebuild the bar when the config is reloaded.
+///
+/// Readings can come with a [Level], which picks the style they're shown in.
+pub struct Polled {
+    inner: Arc<Mutex<Readout>>,
+    interval: Duration,
+    get_reading: Option<GetReading>,
+}
+
+type GetReading = Box<dyn Fn() -> Option<(String, Level)> + Send + 'static>;
+
+/// The latest reading, kept in a [Text] for each [Level].
+#[derive(Debug)]
+struct Readout {
+    texts: [Text; 3],
+    level: Level,
+    level_changed: bool,
+}
+
+impl Readout {
+    fn current(&mut self) -> &mut Text {
+        &mut self.texts[self.level as usize]
+    }
+
+    fn set(&mut self, txt: String, level: Level) {
+        for text in self.texts.iter_mut() {
+            text.set_text(txt.clone());
+        }
+        if level != self.level {
+            self.level = level;
+            self.level_changed = true;
+        }
+    }
+}
+
+impl fmt::Debug for Polled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Polled")
+            .field("inner", &self.inner)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl Polled {
+    pub fn new<F>(style: TextStyle, get_text: F, interval: Duration) -> Self
+    where
+        F: Fn() -> Option<String> + Send + 'static,
+    {
+        Self::with_levels(
+            [style; 3],
+            move || get_text().map(|txt| (txt, Level::Normal)),
+            interval,
+        )
+    }
+
+    /// `styles` are for the normal, warning and critical levels in turn.
+    pub fn with_levels<F>(styles: [TextStyle; 3], get_reading: F, interval: Duration) -> Self
+    where
+        F: Fn() -> Option<(String, Level)> + Send + 'static,
+    {
+        Self {
+            inner: Arc::new(Mutex::new(Readout {
+                texts: styles.map(|style| Text::new("", style, false, true)),
+                level: Level::Normal,
+                level_changed: false,
+            })),
+            interval,
+            get_reading: Some(Box::new(get_reading)),
+        }
+    }
+
+    fn inner_guard(&self) -> MutexGuard<'_, Readout> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<X: XConn> Widget<X> for Polled {
+    fn draw(&mut self, ctx: &mut Context<'_>, s: usize, f: bool, w: u32, h: u32) -> Result<()> {
+        let mut readout = self.inner_guard();
+        readout.level_changed = false;
+        Widget::<X>::draw(readout.current(), ctx, s, f, w, h)
+    }
+
+    fn current_extent(&mut self, ctx: &mut Context<'_>, h: u32) -> Result<(u32, u32)> {
+        Widget::<X>::current_extent(self.inner_guard().current(), ctx, h)
+    }
+
+    fn is_greedy(&self) -> bool {
+        false
+    }
+
+    fn require_draw(&self) -> bool {
+        let mut readout = self.inner_guard();
+        readout.level_changed || Widget::<X>::require_draw(readout.current())
+    }
+
+    fn on_startup(&mut self, _: &mut State<X>, _: &X) -> Result<()> {
+        if let Some(get_reading) = self.get_reading.take() {
+            let readout = Arc::downgrade(&self.inner);
+            let interval = self.interval;
+            thread::spawn(move || loop {
+                let reading = get_reading();
+                match (readout.upgrade(), reading) {
+                    (None, _) => break,
+                    (Some(readout), Some((txt, level))) => readout
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .set(txt, level),
+                    (Some(_), None) => (),
+                }
+                thread::sleep(interval);
+            });
+        }
+
+        Ok(())
+    }
 }
 
 // pub fn weather_text() -> Option<String> {
@@ -354,29 +642,53 @@ impl<X: XConn> Widget<X> for Wedge {
     }
 }
 
+/// The system tray, shared by every bar we build so that docked icons stay
+/// put when the bar is rebuilt.
+static TRAY: Lazy<Option<SystemTray>> =
+    Lazy::new(|| SystemTray::start().log_err("Couldn't start the system tray"));
+
+fn tray_icons() -> usize {
+    TRAY.as_ref().map_or(0, SystemTray::icon_count)
+}
+
+/// Room for the system tray's icons, which are shown over it. This needs to
+/// be the last widget on the bar for `screen`. The tray wakes the window
+/// manager as icons come and go so that the bar is redrawn to fit them.
 #[derive(Debug)]
-pub struct Spacer {
-    perc: f32,
-    w: u32,
+pub struct Tray {
+    screen: Rect,
+    bg: Color,
+    icons: usize,
 }
 
-impl Spacer {
-    pub fn new(perc: f32) -> Self {
-        if !(0.0..=1.0).contains(&perc) {
-            panic!("{perc} is an invalid percentage");
+impl Tray {
+    pub fn new(screen: Rect, bg: impl Into<Color>) -> Self {
+        Self {
+            screen,
+            bg: bg.into(),
+            icons: 0,
         }
-
-        Self { perc, w: 0 }
     }
 }
 
-impl<X: XConn> Widget<X> for Spacer {
+impl<X: XConn> Widget<X> for Tray {
     fn draw(&mut self, ctx: &mut Context<'_>, _: usize, _: bool, w: u32, h: u32) -> Result<()> {
-        ctx.fill_bg(Rect::new(0, 0, w, h))
+        ctx.fill_bg(Rect::new(0, 0, w, h))?;
+        if let Some(tray) = TRAY.as_ref() {
+            tray.place(TrayAnchor {
+                right: self.screen.x + self.screen.w as i32,
+                y: self.screen.y,
+                icon_size: h as u16,
+                bg: self.bg.rgb_u32(),
+            })?;
+        }
+
+        Ok(())
     }
 
     fn current_extent(&mut self, _: &mut Context<'_>, h: u32) -> Result<(u32, u32)> {
-        Ok((self.w, h))
+        self.icons = tray_icons();
+        Ok((self.icons as u32 * h, h))
     }
 
     fn is_greedy(&self) -> bool {
@@ -384,17 +696,6 @@ impl<X: XConn> Widget<X> for Spacer {
     }
 
     fn require_draw(&self) -> bool {
-        false
-    }
-
-    fn on_startup(&mut self, state: &mut State<X>, _: &X) -> Result<()> {
-        self.w = state
-            .client_set
-            .screens()
-            .next()
-            .map(|s| (s.geometry().w as f32 * self.perc) as u32)
-            .unwrap();
-
-        Ok(())
+        tray_icons() != self.icons
     }
 }
@@ -0,0 +1,79 @@
+use dotpenrose::ipc::{socket_path, Response};
+use std::{
+    env,
+    io::{BufRead, BufReader, Lines, Write},
+    os::unix::net::UnixStream,
+    process::ExitCode,
+};
+
+const USAGE: &str = "usage: dotpenrose-msg <action> [args...]
+       dotpenrose-msg query workspaces|clients|screens|focused
+       dotpenrose-msg subscribe [event...]
+
+Actions are written as in the bindings file, e.g.
+  dotpenrose-msg focus_tag 3
+  dotpenrose-msg spawn alacritty
+
+Subscribing prints one JSON event per line, optionally limited to the given
+events: workspace_focused, screen_focused, client_added, client_removed,
+client_moved, title_changed, layout_changed";
+
+type Error = Box<dyn std::error::Error>;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args[0] == "-h" || args[0] == "--help" {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+    let request = match args.as_slice() {
+        [query, what] if query == "query" => serde_json::json!({ "query": what }),
+        [subscribe, events @ ..] if subscribe == "subscribe" => {
+            serde_json::json!({ "subscribe": events })
+        }
+        _ => serde_json::json!({ "command": args.join(" ") }),
+    };
+
+    let subscribe = args[0] == "subscribe";
+
+    let res = send(&request.to_string(), subscribe).and_then(|(response, lines)| {
+        if !response.ok {
+            return Err(response.error.unwrap_or_default().into());
+        }
+        if let Some(result) = response.result {
+            println!("{result:#}");
+        }
+        // Only subscriptions send anything after the response
+        for line in lines {
+            println!("{}", line?);
+        }
+
+        Ok(())
+    });
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn send(request: &str, subscribe: bool) -> Result<(Response, Lines<BufReader<UnixStream>>), Error> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|e| format!("unable to connect to {}: {e}", path.display()))?;
+    writeln!(stream, "{request}")?;
+    // Closing our side ends the session once the window manager has replied
+    if !subscribe {
+        stream.shutdown(std::net::Shutdown::Write)?;
+    }
+
+    let mut lines = BufReader::new(stream).lines();
+    let line = lines
+        .next()
+        .ok_or("no response from the window manager")??;
+
+    Ok((serde_json::from_str(&line)?, lines))
+}
@@ -0,0 +1,552 @@
+use once_cell::sync::Lazy;
+use penrose::{
+    builtin::{
+        actions::{key_handler, modify_with, send_layout_message},
+        layout::messages::{ExpandMain, IncMain, ShrinkMain},
+    },
+    core::{
+        bindings::{
+            keycodes_from_xmodmap, parse_keybindings_with_xmodmap, KeyBindings, KeyCode,
+            ModifierKey,
+        },
+        State, WindowManager,
+    },
+    util::spawn,
+    x::{XConn, XEvent},
+    x11rb::RustConn,
+    Error, Result,
+};
+use penrose_bbarker_contrib::{
+    actions::spawn_in_focused_cwd,
+    log::LogPenroseError,
+    menus::finder::{
+        goto_workspace_by_apps, send_to_workspace_menu, workspace_menu, GOTO_WS_CONFIG,
+    },
+    KeyHandler,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use crate::{
+    reload::reload_config,
+    scratchpad::toggle_scratchpad,
+    session::save_and_exit,
+    theme::{find_theme, next_theme, set_theme},
+    NUM_FAST_ACCESS_WORKSPACES,
+};
+
+static GOTO_WS: Lazy<Box<dyn Fn() -> KeyHandler + Send + Sync>> =
+    Lazy::new(|| goto_workspace_by_apps(&GOTO_WS_CONFIG));
+
+/// Messages that can be sent to the active layout from a binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutMessage {
+    IncMain(i8),
+    ExpandMain,
+    ShrinkMain,
+}
+
+/// The named actions that a key string can be bound to.
+///
+/// In a bindings file these are written as the action name followed by any
+/// arguments, e.g. `"focus_tag 3"`, `"spawn dmenu_run"` or
+/// `"send_layout_message IncMain 1"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Action {
+    FocusUp,
+    FocusDown,
+    SwapUp,
+    SwapDown,
+    SwapFocusAndHead,
+    KillFocused,
+    ToggleTag,
+    NextScreen,
+    PreviousScreen,
+    FocusNextWorkspace,
+    FocusPreviousWorkspace,
+    DragWorkspaceForward,
+    DragWorkspaceBackward,
+    NextLayout,
+    PreviousLayout,
+    SendLayoutMessage(LayoutMessage),
+    FocusTag(String),
+    MoveFocusedToTag(String),
+    Spawn(String),
+    /// Spawn a command with `{cwd}` replaced by the focused window's
+    /// working directory
+    SpawnInFocusedCwd(String),
+    WorkspaceMenu,
+    SendToWorkspaceMenu,
+    GotoWorkspaceByApps,
+    Exit,
+    Reload,
+    NextTheme,
+    SetTheme(String),
+    ToggleScratchpad(String),
+    /// Removes a built-in default binding
+    Unbind,
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        use Action::*;
+
+        let s = s.trim();
+        let (name, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let rest = rest.trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        let err = |msg: &str| Error::Custom(format!("invalid action '{s}': {msg}"));
+        let no_args = |action: Action| {
+            if args.is_empty() {
+                Ok(action)
+            } else {
+                Err(err("takes no arguments"))
+            }
+        };
+        let one_arg = |f: fn(String) -> Action| match args.as_slice() {
+            [arg] => Ok(f(arg.to_string())),
+            _ => Err(err("takes exactly one argument")),
+        };
+
+        match name {
+            "focus_up" => no_args(FocusUp),
+            "focus_down" => no_args(FocusDown),
+            "swap_up" => no_args(SwapUp),
+            "swap_down" => no_args(SwapDown),
+            "swap_focus_and_head" => no_args(SwapFocusAndHead),
+            "kill_focused" => no_args(KillFocused),
+            "toggle_tag" => no_args(ToggleTag),
+            "next_screen" => no_args(NextScreen),
+            "previous_screen" => no_args(PreviousScreen),
+            "focus_next_workspace" => no_args(FocusNextWorkspace),
+            "focus_previous_workspace" => no_args(FocusPreviousWorkspace),
+            "drag_workspace_forward" => no_args(DragWorkspaceForward),
+            "drag_workspace_backward" => no_args(DragWorkspaceBackward),
+            "next_layout" => no_args(NextLayout),
+            "previous_layout" => no_args(PreviousLayout),
+            "send_layout_message" => match args.as_slice() {
+                ["IncMain", n] => n
+                    .parse()
+                    .map(|n| SendLayoutMessage(LayoutMessage::IncMain(n)))
+                    .map_err(|_| err("IncMain expects an integer")),
+                ["ExpandMain"] => Ok(SendLayoutMessage(LayoutMessage::ExpandMain)),
+                ["ShrinkMain"] => Ok(SendLayoutMessage(LayoutMessage::ShrinkMain)),
+                _ => Err(err("expected one of IncMain <n>, ExpandMain, ShrinkMain")),
+            },
+            "focus_tag" => one_arg(FocusTag),
+            "move_focused_to_tag" => one_arg(MoveFocusedToTag),
+            "spawn" if !rest.is_empty() => Ok(Spawn(rest.to_string())),
+            "spawn" => Err(err("missing command")),
+            "spawn_in_focused_cwd" if !rest.is_empty() => Ok(SpawnInFocusedCwd(rest.to_string())),
+            "spawn_in_focused_cwd" => Err(err("missing command")),
+            "workspace_menu" => no_args(WorkspaceMenu),
+            "send_to_workspace_menu" => no_args(SendToWorkspaceMenu),
+            "goto_workspace_by_apps" => no_args(GotoWorkspaceByApps),
+            "exit" => no_args(Exit),
+            "reload" => no_args(Reload),
+            "next_theme" => no_args(NextTheme),
+            "set_theme" => one_arg(SetTheme),
+            "toggle_scratchpad" => one_arg(ToggleScratchpad),
+            "unbind" => no_args(Unbind),
+            _ => Err(err("unknown action")),
+        }
+    }
+}
+
+impl TryFrom<String> for Action {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Action::*;
+
+        match self {
+            FocusUp => write!(f, "focus_up"),
+            FocusDown => write!(f, "focus_down"),
+            SwapUp => write!(f, "swap_up"),
+            SwapDown => write!(f, "swap_down"),
+            SwapFocusAndHead => write!(f, "swap_focus_and_head"),
+            KillFocused => write!(f, "kill_focused"),
+            ToggleTag => write!(f, "toggle_tag"),
+            NextScreen => write!(f, "next_screen"),
+            PreviousScreen => write!(f, "previous_screen"),
+            FocusNextWorkspace => write!(f, "focus_next_workspace"),
+            FocusPreviousWorkspace => write!(f, "focus_previous_workspace"),
+            DragWorkspaceForward => write!(f, "drag_workspace_forward"),
+            DragWorkspaceBackward => write!(f, "drag_workspace_backward"),
+            NextLayout => write!(f, "next_layout"),
+            PreviousLayout => write!(f, "previous_layout"),
+            SendLayoutMessage(LayoutMessage::IncMain(n)) => {
+                write!(f, "send_layout_message IncMain {n}")
+            }
+            SendLayoutMessage(LayoutMessage::ExpandMain) => {
+                write!(f, "send_layout_message ExpandMain")
+            }
+            SendLayoutMessage(LayoutMessage::ShrinkMain) => {
+                write!(f, "send_layout_message ShrinkMain")
+            }
+            FocusTag(tag) => write!(f, "focus_tag {tag}"),
+            MoveFocusedToTag(tag) => write!(f, "move_focused_to_tag {tag}"),
+            Spawn(cmd) => write!(f, "spawn {cmd}"),
+            SpawnInFocusedCwd(cmd) => write!(f, "spawn_in_focused_cwd {cmd}"),
+            WorkspaceMenu => write!(f, "workspace_menu"),
+            SendToWorkspaceMenu => write!(f, "send_to_workspace_menu"),
+            GotoWorkspaceByApps => write!(f, "goto_workspace_by_apps"),
+            Exit => write!(f, "exit"),
+            Reload => write!(f, "reload"),
+            NextTheme => write!(f, "next_theme"),
+            SetTheme(name) => write!(f, "set_theme {name}"),
+            ToggleScratchpad(name) => write!(f, "toggle_scratchpad {name}"),
+            Unbind => write!(f, "unbind"),
+        }
+    }
+}
+
+impl Action {
+    /// Check the parts of an action that can't be checked while parsing it.
+    pub fn validate(&self, tags: &[String]) -> Result<()> {
+        match self {
+            Action::FocusTag(tag) | Action::MoveFocusedToTag(tag) if !tags.contains(tag) => {
+                Err(Error::Custom(format!("unknown tag '{tag}' in '{self}'")))
+            }
+            Action::SetTheme(name) => find_theme(name).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn key_handler(&self) -> KeyHandler {
+        use Action::*;
+
+        match self.clone() {
+            FocusUp => modify_with(|cs| cs.focus_up()),
+            FocusDown => modify_with(|cs| cs.focus_down()),
+            SwapUp => modify_with(|cs| cs.swap_up()),
+            SwapDown => modify_with(|cs| cs.swap_down()),
+            SwapFocusAndHead => modify_with(|cs| cs.swap_focus_and_head()),
+            KillFocused => modify_with(|cs| cs.kill_focused()),
+            ToggleTag => modify_with(|cs| cs.toggle_tag()),
+            NextScreen => modify_with(|cs| cs.next_screen()),
+            PreviousScreen => modify_with(|cs| cs.previous_screen()),
+            FocusNextWorkspace => modify_with(|cs| cs.focus_next_workspace()),
+            FocusPreviousWorkspace => modify_with(|cs| cs.focus_previous_workspace()),
+            DragWorkspaceForward => modify_with(|cs| cs.drag_workspace_forward()),
+            DragWorkspaceBackward => modify_with(|cs| cs.drag_workspace_backward()),
+            NextLayout => modify_with(|cs| cs.next_layout()),
+            PreviousLayout => modify_with(|cs| cs.previous_layout()),
+            SendLayoutMessage(LayoutMessage::IncMain(n)) => send_layout_message(move || IncMain(n)),
+            SendLayoutMessage(LayoutMessage::ExpandMain) => send_layout_message(|| ExpandMain),
+            SendLayoutMessage(LayoutMessage::ShrinkMain) => send_layout_message(|| ShrinkMain),
+            FocusTag(tag) => modify_with(move |cs| cs.focus_tag(&tag)),
+            MoveFocusedToTag(tag) => modify_with(move |cs| cs.move_focused_to_tag(&tag)),
+            Spawn(cmd) => key_handler(move |_, _| spawn(cmd.as_str())),
+            SpawnInFocusedCwd(cmd) => spawn_in_focused_cwd(cmd),
+            WorkspaceMenu => workspace_menu(),
+            SendToWorkspaceMenu => send_to_workspace_menu(),
+            GotoWorkspaceByApps => GOTO_WS(),
+            Exit => key_handler(save_and_exit),
+            Reload => key_handler(reload_config),
+            NextTheme => key_handler(next_theme),
+            SetTheme(name) => key_handler(move |state, x| set_theme(&name, state, x)),
+            ToggleScratchpad(name) => {
+                key_handler(move |state, x| toggle_scratchpad(&name, state, x))
+            }
+            Unbind => key_handler(|_, _| Ok(())),
+        }
+    }
+}
+
+/// The built-in bindings, used for any key not set in the bindings file.
+pub fn default_bindings() -> BTreeMap<String, Action> {
+    use Action::*;
+
+    let action_bindings = [
+        ("M-f", GotoWorkspaceByApps),
+        ("M-g", WorkspaceMenu),
+        ("M-S-g", SendToWorkspaceMenu),
+        ("M-Left", FocusPreviousWorkspace),
+        ("M-Right", FocusNextWorkspace),
+        ("M-n", FocusDown),
+        ("M-a", FocusUp),
+        ("M-S-n", SwapDown),
+        ("M-S-a", SwapUp),
+        ("M-S-c", KillFocused),
+        ("M-Tab", ToggleTag),
+        ("M-m", NextScreen),
+        ("M-i", PreviousScreen),
+        ("M-s", DragWorkspaceForward),
+        ("M-S-s", DragWorkspaceBackward),
+        ("M-space", NextLayout),
+        ("M-S-space", PreviousLayout),
+        ("M-S-Up", SendLayoutMessage(LayoutMessage::IncMain(1))),
+        ("M-S-Down", SendLayoutMessage(LayoutMessage::IncMain(-1))),
+        ("M-l", SendLayoutMessage(LayoutMessage::ExpandMain)),
+        ("M-h", SendLayoutMessage(LayoutMessage::ShrinkMain)),
+        ("M-Return", SwapFocusAndHead),
+        ("M-p", Spawn("dmenu_run".to_string())),
+        // ("M-p", Spawn("yeganesh -x".to_string())), // not working for some reason
+        ("M-S-z", Spawn("xscreensaver-command -lock".to_string())),
+        (
+            "M-S-Return",
+            SpawnInFocusedCwd("alacritty --working-directory {cwd}".to_string()),
+        ),
+        ("M-A-Escape", Exit),
+        ("M-S-r", Reload),
+        ("M-S-t", NextTheme),
+        ("M-grave", ToggleScratchpad("terminal".to_string())),
+    ]
+    .map(|(key, action)| (key.to_string(), action));
+
+    (1..=NUM_FAST_ACCESS_WORKSPACES)
+        .map(|ws| ws.to_string())
+        .flat_map(|tag| {
+            [
+                (format!("M-{tag}"), FocusTag(tag.clone())),
+                (format!("M-S-{tag}"), MoveFocusedToTag(tag)),
+            ]
+        })
+        .chain(action_bindings)
+        .collect()
+}
+
+fn check_key(key: &str, keycodes: &HashMap<String, u8>) -> Result<()> {
+    let mut parts: Vec<&str> = key.split('-').collect();
+    let name = parts.remove(parts.len() - 1);
+    if !keycodes.contains_key(name) {
+        return Err(Error::UnknownKeyName {
+            name: name.to_owned(),
+        });
+    }
+
+    parts
+        .into_iter()
+        .try_for_each(|modifier| ModifierKey::try_from(modifier).map(|_| ()))
+}
+
+/// Validate string bindings and convert them into penrose [KeyBindings].
+///
+/// Invalid bindings are logged and skipped rather than preventing the whole
+/// set from loading.
+pub fn key_bindings(
+    bindings: &BTreeMap<String, Action>,
+    tags: &[String],
+) -> Result<KeyBindings<RustConn>> {
+    let keycodes = keycodes_from_xmodmap()?;
+    let raw_bindings = bindings
+        .iter()
+        .filter(|(_, action)| **action != Action::Unbind)
+        .filter(|(key, action)| {
+            check_key(key, &keycodes)
+                .and_then(|_| action.validate(tags))
+                .log_err(&format!("Skipping binding \"{key}\" => \"{action}\""))
+                .is_some()
+        })
+        .map(|(key, action)| (key.clone(), action.key_handler()))
+        .collect::<HashMap<String, KeyHandler>>();
+
+    parse_keybindings_with_xmodmap(raw_bindings)
+}
+
+/// Key bindings that can be replaced while the window manager is running.
+///
+/// penrose owns the [KeyBindings] passed to `WindowManager::new` for the
+/// lifetime of the event loop, so to support reloading we hand it an empty
+/// set and dispatch key presses ourselves from an event hook, looking the
+/// handler up in this state extension.
+pub struct Bindings {
+    source: BTreeMap<String, Action>,
+    tags: Vec<String>,
+    keys: KeyBindings<RustConn>,
+    generation: usize,
+}
+
+impl fmt::Debug for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bindings")
+            .field("source", &self.source)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+impl Bindings {
+    pub fn try_new(source: BTreeMap<String, Action>, tags: Vec<String>) -> Result<Self> {
+        let keys = key_bindings(&source, &tags)?;
+
+        Ok(Self {
+            source,
+            tags,
+            keys,
+            generation: 0,
+        })
+    }
+
+    /// Add these bindings to the given [WindowManager] along with the hooks
+    /// needed to drive them.
+    ///
+    /// Add this before the status bar so that the bar's event hook still sees
+    /// every event.
+    pub fn add_to(self, mut wm: WindowManager<RustConn>) -> WindowManager<RustConn> {
+        wm.state.add_extension(self);
+        wm.state.config.compose_or_set_event_hook(event_hook);
+        wm.state.config.compose_or_set_startup_hook(startup_hook);
+
+        wm
+    }
+
+    fn grab(&self, x: &RustConn) -> Result<()> {
+        let codes: Vec<KeyCode> = self.keys.keys().copied().collect();
+        // Mouse bindings are grabbed by penrose and are left alone here
+        x.grab(&codes, &[])
+    }
+}
+
+/// Swap out the active key bindings, re-grabbing keys from the X server.
+pub fn replace_bindings(
+    source: BTreeMap<String, Action>,
+    tags: Vec<String>,
+    state: &mut State<RustConn>,
+    x: &RustConn,
+) -> Result<()> {
+    let mut new_bindings = Bindings::try_new(source, tags)?;
+    new_bindings.grab(x)?;
+
+    let bindings = state.extension::<Bindings>()?;
+    let mut bindings = bindings.borrow_mut();
+    new_bindings.generation = bindings.generation + 1;
+    *bindings = new_bindings;
+
+    Ok(())
+}
+
+fn startup_hook(state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    let bindings = state.extension::<Bindings>()?;
+    let res = bindings.borrow().grab(x);
+
+    res
+}
+
+fn event_hook(event: &XEvent, state: &mut State<RustConn>, x: &RustConn) -> Result<bool> {
+    match event {
+        XEvent::KeyPress(code) => {
+            let bindings = state.extension::<Bindings>()?;
+            // The handler is taken out while it runs so that it is free to
+            // replace the bindings (e.g. when reloading the config).
+            let (handler, generation) = {
+                let mut b = bindings.borrow_mut();
+                (b.keys.remove(code), b.generation)
+            };
+            let Some(mut handler) = handler else {
+                return Ok(false);
+            };
+
+            let res = handler.call(state, x);
+            let mut b = bindings.borrow_mut();
+            if b.generation == generation {
+                b.keys.insert(*code, handler);
+            }
+
+            res.map(|_| false)
+        }
+
+        XEvent::MappingNotify => {
+            let (source, tags) = {
+                let b = state.extension::<Bindings>()?;
+                let b = b.borrow();
+                (b.source.clone(), b.tags.clone())
+            };
+            replace_bindings(source, tags, state, x)?;
+
+            // The default handling would re-grab penrose's (empty) bindings
+            Ok(false)
+        }
+
+        _ => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags() -> Vec<String> {
+        ["1", "2", "web"].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn spawn_keeps_the_whole_command() {
+        let action: Action = r#" spawn  sh -c "echo 'a  b'" "#.parse().unwrap();
+
+        assert_eq!(action, Action::Spawn(r#"sh -c "echo 'a  b'""#.to_string()));
+    }
+
+    #[test]
+    fn layout_messages_are_parsed() {
+        assert_eq!(
+            "send_layout_message IncMain -1".parse::<Action>().unwrap(),
+            Action::SendLayoutMessage(LayoutMessage::IncMain(-1))
+        );
+        assert_eq!(
+            "send_layout_message ShrinkMain".parse::<Action>().unwrap(),
+            Action::SendLayoutMessage(LayoutMessage::ShrinkMain)
+        );
+        assert!("send_layout_message IncMain one".parse::<Action>().is_err());
+        assert!("send_layout_message Rotate".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn focus_tag_must_name_a_known_tag() {
+        let action: Action = "focus_tag web".parse().unwrap();
+        assert!(action.validate(&tags()).is_ok());
+
+        let action: Action = "move_focused_to_tag 10".parse().unwrap();
+        assert!(action.validate(&tags()).is_err());
+        assert!("focus_tag".parse::<Action>().is_err());
+        assert!("focus_tag 1 2".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn invalid_actions_are_rejected() {
+        for s in [
+            "",
+            "fly_away",
+            "focus_up now",
+            "spawn",
+            "spawn_in_focused_cwd  ",
+        ] {
+            assert!(s.parse::<Action>().is_err(), "'{s}' should be rejected");
+        }
+    }
+
+    #[test]
+    fn actions_are_displayed_as_they_are_parsed() {
+        let actions = default_bindings()
+            .into_values()
+            .chain([Action::SetTheme("gruvbox".to_string()), Action::Unbind]);
+
+        for action in actions {
+            assert_eq!(action.to_string().parse::<Action>().unwrap(), action);
+        }
+    }
+
+    #[test]
+    fn actions_deserialize_from_strings() {
+        let bindings: BTreeMap<String, Action> =
+            serde_json::from_str(r#"{ "M-p": "spawn dmenu_run", "M-S-z": "unbind" }"#).unwrap();
+
+        assert_eq!(bindings["M-p"], Action::Spawn("dmenu_run".to_string()));
+        assert_eq!(bindings["M-S-z"], Action::Unbind);
+        assert!(serde_json::from_str::<Action>(r#""nope""#).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use penrose::{extensions::hooks::named_scratchpads::NSP_TAG, Error, Result};
+use penrose_bbarker_contrib::{
+    log::LogPenroseError,
+    menus::backend::MenuKind,
+    outputs::{validate_outputs, OutputConfig},
+    rules::WindowRule,
+    services::{validate_services, Service},
+    swallow::{SwallowConfig, SWALLOWED_TAG},
+    HOSTNAME,
+};
+use serde::Deserialize;
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+use toml::{Table, Value};
+
+use crate::{
+    bar::{
+        BAR_HEIGHT_PX_EXTERNAL, BAR_HEIGHT_PX_PRIMARY, BAR_POINT_SIZE_EXTERNAL,
+        BAR_POINT_SIZE_PRIMARY, MAX_ACTIVE_WINDOW_CHARS,
+    },
+    bindings::{default_bindings, Action},
+    icons::IconConfig,
+    schedule::ColorSchedule,
+    scratchpad::{default_scratchpads, Scratchpad},
+    theme::{find_theme, ColorOverrides, Theme, DEFAULT_THEME},
+    ALL_TAGS, FONT,
+};
+
+/// `$XDG_CONFIG_HOME/dotpenrose/config.toml`, falling back to `~/.config`.
+pub fn config_path() -> Option<PathBuf> {
+    env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok()
+        .map(|dir| dir.join("dotpenrose").join("config.toml"))
+}
+
+/// Settings read from the user's config file. Anything missing from the file
+/// takes its built-in default, and a `[hosts.<hostname>]` profile can override
+/// any of them: see [UserConfig::from_toml_for_host].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// Merged over [default_bindings]; bind a key to `"unbind"` to drop a default.
+    pub bindings: BTreeMap<String, Action>,
+    /// Workspace tags, in order.
+    pub tags: Vec<String>,
+    /// One of the bundled themes ("kanagawa", "gruvbox") or the name of a
+    /// file in [themes_dir](crate::theme::themes_dir)
+    pub theme: String,
+    /// Individual colors replacing those of the theme
+    pub colors: ColorOverrides,
+    /// Times of day when colors are swapped out, the first active one wins
+    pub color_schedule: Vec<ColorSchedule>,
+    /// Where to put windows as they are opened, applied in order
+    pub rules: Vec<WindowRule>,
+    /// Terminals whose place is taken by the windows of programs run in them
+    pub swallow: SwallowConfig,
+    /// The program that menus are shown with: "dmenu_rs", "dmenu", "rofi" or
+    /// "fzf", which runs in a floating terminal
+    pub menu: MenuKind,
+    /// Programs toggled as floating windows with `toggle_scratchpad <name>`.
+    /// Changes only take effect when the window manager is restarted.
+    pub scratchpads: Vec<Scratchpad>,
+    /// Programs started and looked after by the window manager. Changes only
+    /// take effect when the window manager is restarted.
+    pub services: Vec<Service>,
+    /// Monitor layout, applied at startup, on reload and when monitors are
+    /// plugged in or removed. Monitors that aren't listed are left alone.
+    pub outputs: Vec<OutputConfig>,
+    pub bar: BarConfig,
+    /// Icons shown next to workspace tags in the bar
+    pub icons: IconConfig,
+}
+
+impl Default for UserConfig {
+    fn default() -> Self {
+        Self {
+            bindings: BTreeMap::new(),
+            tags: ALL_TAGS.clone(),
+            theme: DEFAULT_THEME.to_string(),
+            colors: ColorOverrides::default(),
+            color_schedule: Vec::new(),
+            rules: Vec::new(),
+            swallow: SwallowConfig::default(),
+            menu: MenuKind::default(),
+            scratchpads: default_scratchpads(),
+            services: default_services(),
+            outputs: Vec::new(),
+            bar: BarConfig::default(),
+            icons: IconConfig::default(),
+        }
+    }
+}
+
+/// The widgets that can be placed on the status bar, left to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BarWidget {
+    Workspaces,
+    CurrentLayout,
+    ActiveWindow,
+    Wifi,
+    Battery,
+    Volume,
+    DateTime,
+    Cpu,
+    /// A sparkline of usage per core
+    CpuCores,
+    /// Memory and swap use
+    Memory,
+    LoadAverage,
+    /// One for each of `disk_mounts`
+    Disk,
+    Temperature,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarConfig {
+    pub font: String,
+    pub height_primary: u32,
+    pub height_external: u32,
+    pub point_size_primary: u8,
+    pub point_size_external: u8,
+    pub max_active_window_chars: usize,
+    /// Screen to show the system tray on, by its index in RandR's list of
+    /// monitors. Defaults to the primary screen.
+    pub tray_screen: Option<usize>,
+    pub widgets: Vec<BarWidget>,
+    /// Percentages at or below which the battery is shown in the theme's
+    /// warning and urgent colors while not charging
+    pub battery_warning: u8,
+    pub battery_critical: u8,
+    /// The same for wifi signal quality
+    pub wifi_warning: u8,
+    pub wifi_critical: u8,
+    /// The ALSA card and mixer control that the volume is read from
+    pub volume_card: u32,
+    pub volume_control: String,
+    /// Mount points to show the disk usage of
+    pub disk_mounts: Vec<String>,
+    pub disk_warning: u8,
+    pub disk_critical: u8,
+    /// Part of the label of the hwmon sensor to show, such as "Package id 0".
+    /// Defaults to the hottest sensor.
+    pub temperature_sensor: Option<String>,
+    /// In °C
+    pub temperature_warning: u8,
+    pub temperature_critical: u8,
+}
+
+impl Default for BarConfig {
+    fn default() -> Self {
+        use BarWidget::*;
+
+        Self {
+            font: FONT.to_string(),
+            height_primary: BAR_HEIGHT_PX_PRIMARY,
+            height_external: BAR_HEIGHT_PX_EXTERNAL,
+            point_size_primary: BAR_POINT_SIZE_PRIMARY,
+            point_size_external: BAR_POINT_SIZE_EXTERNAL,
+            max_active_window_chars: MAX_ACTIVE_WINDOW_CHARS,
+            tray_screen: None,
+            widgets: vec![
+                Workspaces,
+                CurrentLayout,
+                ActiveWindow,
+                Wifi,
+                Battery,
+                Volume,
+                DateTime,
+            ],
+            battery_warning: 25,
+            battery_critical: 10,
+            wifi_warning: 40,
+            wifi_critical: 20,
+            volume_card: 0,
+            volume_control: "Master".to_string(),
+            disk_mounts: vec!["/".to_string()],
+            disk_warning: 85,
+            disk_critical: 95,
+            temperature_sensor: None,
+            temperature_warning: 75,
+            temperature_critical: 90,
+        }
+    }
+}
+
+pub fn default_services() -> Vec<Service> {
+    let service = |name: &str, args: &[&str]| Service {
+        name: name.to_string(),
+        command: name.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        only_if_missing: true,
+        depends_on: Vec::new(),
+        restart_on_exit: false,
+        once_per_session: false,
+        hosts: Vec::new(),
+    };
+
+    vec![
+        service("xscreensaver", &[]),
+        service("nvidia-settings", &["--load-config-only"]),
+        Service {
+            restart_on_exit: true,
+            ..service("picom", &[])
+        },
+        // A new random wallpaper with each session, not each restart
+        Service {
+            once_per_session: true,
+            ..service(
+                "nitrogen",
+                &["--set-zoom-fill", "--random", "${PENROSE_DIR}/wallpapers"],
+            )
+        },
+    ]
+}
+
+/// `overlay` merged over `base`, recursing into tables that both have.
+fn merged(mut base: Table, overlay: Table) -> Table {
+    for (key, value) in overlay {
+        let value = match (base.remove(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => {
+                Value::Table(merged(base, overlay))
+            }
+            (_, value) => value,
+        };
+        base.insert(key, value);
+    }
+
+    base
+}
+
+impl UserConfig {
+    /// Parse a config file, applying the profile for this machine's
+    /// [HOSTNAME] if there is one.
+    pub fn from_toml(toml_str: &str) -> Result<Self> {
+        Self::from_toml_for_host(toml_str, &HOSTNAME)
+    }
+
+    /// Parse a config file as it applies to `host`.
+    ///
+    /// Settings under `[hosts.<name>]` are merged over the rest of the file
+    /// on the machine with that hostname: tables such as `bindings` are
+    /// merged key by key and anything else, such as `tags` or
+    /// `bar.widgets`, is replaced. Every profile is checked so that a mistake
+    /// in one is caught on any machine.
+    pub fn from_toml_for_host(toml_str: &str, host: &str) -> Result<Self> {
+        let invalid = |msg: String| Error::Custom(format!("invalid config: {msg}"));
+        let mut base: Table = toml::from_str(toml_str).map_err(|e| invalid(e.to_string()))?;
+        let profiles = match base.remove("hosts") {
+            Some(Value::Table(profiles)) => profiles,
+            Some(_) => return Err(invalid("`hosts` must be a table".to_string())),
+            None => Table::new(),
+        };
+
+        let parse = |table: Table| -> Result<Self> {
+            let config: Self = table.try_into().map_err(|e| invalid(e.to_string()))?;
+            config.validate()?;
+            Ok(config)
+        };
+
+        let mut for_host = None;
+        for (name, profile) in profiles {
+            let Value::Table(profile) = profile else {
+                return Err(invalid(format!("host profile '{name}' must be a table")));
+            };
+            let merged = parse(merged(base.clone(), profile))
+                .map_err(|e| Error::Custom(format!("in host profile '{name}': {e}")))?;
+            if name == host {
+                for_host = Some(merged);
+            }
+        }
+
+        match for_host {
+            Some(config) => Ok(config),
+            None => parse(base),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |msg: String| Err(Error::Custom(format!("invalid config: {msg}")));
+        let mut tags = self.tags.clone();
+        tags.sort();
+        tags.dedup();
+        let unknown_rule_tag = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.tag.as_ref())
+            .find(|tag| !self.tags.contains(tag));
+        let mut scratchpads: Vec<&str> =
+            self.scratchpads.iter().map(|sp| sp.name.as_str()).collect();
+        scratchpads.sort();
+        scratchpads.dedup();
+        let bar = &self.bar;
+
+        if self.tags.is_empty() {
+            invalid("at least one tag is required".to_string())
+        } else if tags.len() != self.tags.len() {
+            invalid(format!("duplicate tags in {:?}", self.tags))
+        } else if self.tags.iter().any(|tag| tag == NSP_TAG) {
+            invalid(format!("the tag '{NSP_TAG}' is reserved for scratchpads"))
+        } else if self.tags.iter().any(|tag| tag == SWALLOWED_TAG) {
+            invalid(format!(
+                "the tag '{SWALLOWED_TAG}' is reserved for swallowed windows"
+            ))
+        } else if let Some(tag) = unknown_rule_tag {
+            invalid(format!("window rule has unknown tag '{tag}'"))
+        } else if scratchpads.len() != self.scratchpads.len() {
+            invalid("duplicate scratchpad names".to_string())
+        } else if let Some(e) = self.scratchpads.iter().find_map(|sp| sp.validate().err()) {
+            invalid(e.to_string())
+        } else if let Err(e) = validate_services(&self.services) {
+            invalid(e.to_string())
+        } else if let Err(e) = validate_outputs(&self.outputs) {
+            invalid(e.to_string())
+        } else if let Err(e) = find_theme(&self.theme) {
+            invalid(e.to_string())
+        } else if !(bar.battery_critical <= bar.battery_warning && bar.battery_warning <= 100) {
+            invalid("battery thresholds need critical <= warning <= 100".to_string())
+        } else if !(bar.wifi_critical <= bar.wifi_warning && bar.wifi_warning <= 100) {
+            invalid("wifi thresholds need critical <= warning <= 100".to_string())
+        } else if !(bar.disk_warning <= bar.disk_critical && bar.disk_critical <= 100) {
+            invalid("disk thresholds need warning <= critical <= 100".to_string())
+        } else if bar.temperature_warning > bar.temperature_critical {
+            invalid("temperature thresholds need warning <= critical".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read the config file, if there is one. Errors are logged and the
+    /// defaults are used instead so that a typo can't stop the WM starting.
+    pub fn load() -> Self {
+        Self::try_load()
+            .log_err("Couldn't load the config file")
+            .unwrap_or_default()
+    }
+
+    /// Read the config file, using the defaults only if there isn't one.
+    pub fn try_load() -> Result<Self> {
+        match config_path() {
+            Some(path) if path.exists() => fs::read_to_string(&path)
+                .map_err(Error::from)
+                .and_then(|contents| Self::from_toml(&contents))
+                .map_err(|e| Error::Custom(format!("{}: {e}", path.display()))),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// The selected theme with any color overrides applied.
+    pub fn theme(&self) -> Theme {
+        let theme = find_theme(&self.theme)
+            .log_err("Couldn't load theme")
+            .unwrap_or_default();
+
+        self.colors.apply_to(theme)
+    }
+
+    pub fn key_bindings(&self) -> BTreeMap<String, Action> {
+        let mut bindings = default_bindings();
+        bindings.extend(self.bindings.clone());
+        bindings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        tags = ["1", "2", "3"]
+
+        [bindings]
+        "M-p" = "spawn dmenu_run"
+        "M-q" = "exit"
+
+        [bar]
+        height_primary = 20
+        widgets = ["workspaces", "battery", "date_time"]
+
+        [hosts.desktop]
+        tags = ["1", "web"]
+
+        [hosts.desktop.bindings]
+        "M-p" = "spawn rofi -show run"
+
+        [hosts.desktop.bar]
+        widgets = ["workspaces", "date_time"]
+    "#;
+
+    fn spawn(cmd: &str) -> Action {
+        Action::Spawn(cmd.to_string())
+    }
+
+    #[test]
+    fn host_tables_are_merged_key_by_key() {
+        let config = UserConfig::from_toml_for_host(CONFIG, "desktop").unwrap();
+
+        assert_eq!(config.bindings["M-p"], spawn("rofi -show run"));
+        assert_eq!(config.bindings["M-q"], Action::Exit);
+        assert_eq!(config.bar.height_primary, 20);
+    }
+
+    #[test]
+    fn host_arrays_replace_the_base() {
+        let config = UserConfig::from_toml_for_host(CONFIG, "desktop").unwrap();
+
+        assert_eq!(config.tags, vec!["1", "web"]);
+        assert_eq!(
+            config.bar.widgets,
+            vec![BarWidget::Workspaces, BarWidget::DateTime]
+        );
+    }
+
+    #[test]
+    fn other_hosts_get_the_base_config() {
+        let config = UserConfig::from_toml_for_host(CONFIG, "laptop").unwrap();
+
+        assert_eq!(config.tags, vec!["1", "2", "3"]);
+        assert_eq!(config.bindings["M-p"], spawn("dmenu_run"));
+        assert_eq!(
+            config.bar.widgets,
+            vec![
+                BarWidget::Workspaces,
+                BarWidget::Battery,
+                BarWidget::DateTime
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected_on_every_host() {
+        let config = format!("{CONFIG}\n[hosts.laptop]\ntags = []\n");
+
+        for host in ["laptop", "desktop", "server"] {
+            let err = UserConfig::from_toml_for_host(&config, host)
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("host profile 'laptop'"), "{err}");
+        }
+    }
+
+    #[test]
+    fn profiles_must_be_tables() {
+        assert!(UserConfig::from_toml_for_host("hosts = 1", "desktop").is_err());
+        assert!(UserConfig::from_toml_for_host("[hosts]\ndesktop = 1", "desktop").is_err());
+    }
+
+    #[test]
+    fn reserved_and_duplicate_tags_are_rejected() {
+        for tags in [vec!["1", "1"], vec![NSP_TAG], vec![SWALLOWED_TAG], vec![]] {
+            let config = format!("tags = {tags:?}");
+            assert!(UserConfig::from_toml_for_host(&config, "desktop").is_err());
+        }
+        assert!(UserConfig::from_toml_for_host(r#"tags = ["1", "web"]"#, "desktop").is_ok());
+    }
+}
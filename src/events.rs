@@ -0,0 +1,204 @@
+use penrose::{
+    core::{Config, State},
+    x::{XConn, XConnExt, XEvent},
+    Error, Xid,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+/// Something that changed in the window manager, sent to subscribers of the
+/// IPC socket as a line of JSON such as
+/// `{"event":"workspace_focused","tag":"3","screen":0}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    WorkspaceFocused { tag: String, screen: usize },
+    ScreenFocused { screen: usize },
+    ClientAdded { id: Xid, tag: String, title: String },
+    ClientRemoved { id: Xid },
+    ClientMoved { id: Xid, tag: String },
+    TitleChanged { id: Xid, title: String },
+    LayoutChanged { tag: String, layout: String },
+}
+
+/// The kinds of [Event] that a subscriber can ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    WorkspaceFocused,
+    ScreenFocused,
+    ClientAdded,
+    ClientRemoved,
+    ClientMoved,
+    TitleChanged,
+    LayoutChanged,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::WorkspaceFocused { .. } => EventKind::WorkspaceFocused,
+            Event::ScreenFocused { .. } => EventKind::ScreenFocused,
+            Event::ClientAdded { .. } => EventKind::ClientAdded,
+            Event::ClientRemoved { .. } => EventKind::ClientRemoved,
+            Event::ClientMoved { .. } => EventKind::ClientMoved,
+            Event::TitleChanged { .. } => EventKind::TitleChanged,
+            Event::LayoutChanged { .. } => EventKind::LayoutChanged,
+        }
+    }
+}
+
+struct Subscriber {
+    kinds: Vec<EventKind>,
+    tx: Sender<Event>,
+}
+
+/// Everyone currently listening for events. This is shared between the IPC
+/// socket threads and the window manager hooks that publish events.
+#[derive(Clone, Default)]
+pub struct Subscribers {
+    inner: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl Subscribers {
+    /// Receive every event of the given kinds (or all events if `kinds` is
+    /// empty) until the receiver is dropped.
+    pub fn subscribe(&self, kinds: Vec<EventKind>) -> Receiver<Event> {
+        let (tx, rx) = channel();
+        if let Ok(mut subscribers) = self.inner.lock() {
+            subscribers.push(Subscriber { kinds, tx });
+        }
+
+        rx
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().map(|s| s.is_empty()).unwrap_or(true)
+    }
+
+    pub fn publish(&self, event: Event) {
+        if let Ok(mut subscribers) = self.inner.lock() {
+            let kind = event.kind();
+            subscribers.retain(|s| {
+                !(s.kinds.is_empty() || s.kinds.contains(&kind)) || s.tx.send(event.clone()).is_ok()
+            });
+        }
+    }
+}
+
+/// What we compare between refreshes to work out which events to send.
+#[derive(Default, PartialEq)]
+struct Snapshot {
+    tag: String,
+    screen: usize,
+    clients: BTreeMap<Xid, String>,
+    layouts: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    /// Only the user's tags are looked at, so clients going to or from the
+    /// hidden scratchpad and swallowed workspaces are added or removed.
+    fn new<X: XConn>(state: &State<X>) -> Self {
+        let cs = &state.client_set;
+
+        Self {
+            tag: cs.current_tag().to_string(),
+            screen: cs.current_screen().index(),
+            clients: cs
+                .non_hidden_workspaces()
+                .flat_map(|ws| ws.clients().map(|&id| (id, ws.tag().to_string())))
+                .collect(),
+            layouts: cs
+                .non_hidden_workspaces()
+                .map(|ws| (ws.tag().to_string(), ws.layout_name()))
+                .collect(),
+        }
+    }
+}
+
+/// Publish events to `subscribers` as the window manager state changes.
+pub fn add_event_hooks<X>(mut config: Config<X>, subscribers: Subscribers) -> Config<X>
+where
+    X: XConn + 'static,
+{
+    let mut prev = Snapshot::default();
+    let subs = subscribers.clone();
+    config.compose_or_set_refresh_hook(move |state: &mut State<X>, x: &X| {
+        let next = Snapshot::new(state);
+        if next != prev && !subs.is_empty() {
+            publish_changes(&prev, &next, &subs, x);
+        }
+        prev = next;
+
+        Ok(())
+    });
+
+    config.compose_or_set_event_hook(move |event: &XEvent, state: &mut State<X>, x: &X| {
+        match event {
+            XEvent::PropertyNotify(p)
+                if (p.atom == "WM_NAME" || p.atom == "_NET_WM_NAME")
+                    && state.client_set.contains(&p.id)
+                    && !subscribers.is_empty() =>
+            {
+                subscribers.publish(Event::TitleChanged {
+                    id: p.id,
+                    title: x.window_title(p.id).unwrap_or_default(),
+                });
+            }
+            _ => (),
+        }
+
+        Ok::<_, Error>(true)
+    });
+
+    config
+}
+
+fn publish_changes<X: XConn>(prev: &Snapshot, next: &Snapshot, subs: &Subscribers, x: &X) {
+    for (&id, tag) in next.clients.iter() {
+        match prev.clients.get(&id) {
+            None => subs.publish(Event::ClientAdded {
+                id,
+                tag: tag.clone(),
+                title: x.window_title(id).unwrap_or_default(),
+            }),
+            Some(prev_tag) if prev_tag != tag => subs.publish(Event::ClientMoved {
+                id,
+                tag: tag.clone(),
+            }),
+            _ => (),
+        }
+    }
+    for &id in prev.clients.keys() {
+        if !next.clients.contains_key(&id) {
+            subs.publish(Event::ClientRemoved { id });
+        }
+    }
+
+    for (tag, layout) in next.layouts.iter() {
+        if prev.layouts.get(tag).is_some_and(|prev| prev != layout) {
+            subs.publish(Event::LayoutChanged {
+                tag: tag.clone(),
+                layout: layout.clone(),
+            });
+        }
+    }
+
+    if next.screen != prev.screen {
+        subs.publish(Event::ScreenFocused {
+            screen: next.screen,
+        });
+    }
+    if next.tag != prev.tag {
+        subs.publish(Event::WorkspaceFocused {
+            tag: next.tag.clone(),
+            screen: next.screen,
+        });
+    }
+}
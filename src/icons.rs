@@ -0,0 +1,259 @@
+use penrose_bbarker_contrib::{rules::TitleRegex, workspaces::ClientInfo};
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// What an [IconRule] matches a client against.
+///
+/// In the config file this is written as one of `exe`, `exe_contains`,
+/// `title_contains`, `title_regex` or `class` alongside the icon.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IconMatch {
+    /// The executable name, exactly
+    Exe(String),
+    ExeContains(String),
+    TitleContains(String),
+    TitleRegex(TitleRegex),
+    /// Either of the `WM_CLASS` strings, exactly
+    Class(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IconRule {
+    pub icon: String,
+    #[serde(flatten)]
+    pub matcher: IconMatch,
+    /// When a client matches several rules the highest priority wins, and
+    /// icons are shown in priority order. Ties go to the earlier rule.
+    #[serde(default)]
+    pub priority: i32,
+    /// Show how many clients matched, e.g. "🦊×3", rather than a single icon
+    #[serde(default)]
+    pub count: bool,
+}
+
+impl IconRule {
+    pub fn matches(&self, client: &ClientInfo) -> bool {
+        let exe = client.exe.as_deref().unwrap_or_default();
+        match &self.matcher {
+            IconMatch::Exe(s) => exe == s,
+            IconMatch::ExeContains(s) => exe.contains(s.as_str()),
+            IconMatch::TitleContains(s) => client.title.contains(s.as_str()),
+            IconMatch::TitleRegex(re) => re.is_match(&client.title),
+            IconMatch::Class(class) => client.has_class(class),
+        }
+    }
+}
+
+/// Icons are sorted by priority then rule index, with the fallback last.
+type SortKey = (Reverse<i32>, usize);
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IconConfig {
+    pub rules: Vec<IconRule>,
+    /// Shown for clients that don't match any rule
+    pub fallback: Option<String>,
+    /// Show each icon once per workspace rather than once per client
+    pub dedupe: bool,
+}
+
+impl Default for IconConfig {
+    fn default() -> Self {
+        let rule = |icon: &str, matcher| IconRule {
+            icon: icon.to_string(),
+            matcher,
+            priority: 0,
+            count: false,
+        };
+
+        Self {
+            rules: vec![
+                rule("🎵", IconMatch::ExeContains("spotify".to_string())),
+                rule("🦊", IconMatch::ExeContains("firefox".to_string())),
+                rule(
+                    "󰧷",
+                    IconMatch::TitleContains("LibreOffice Calc".to_string()),
+                ),
+                rule(
+                    "",
+                    IconMatch::TitleContains("LibreOffice Writer".to_string()),
+                ),
+            ],
+            fallback: None,
+            dedupe: true,
+        }
+    }
+}
+
+impl IconConfig {
+    /// The icons for a workspace holding the given clients.
+    pub fn icons<'a>(&self, clients: impl IntoIterator<Item = &'a ClientInfo>) -> String {
+        let mut matched: Vec<(SortKey, &str, bool)> = Vec::new();
+
+        for client in clients {
+            let best = self
+                .rules
+                .iter()
+                .enumerate()
+                .filter(|(_, rule)| rule.matches(client))
+                .min_by_key(|(ix, rule)| (Reverse(rule.priority), *ix));
+
+            match (best, &self.fallback) {
+                (Some((ix, rule)), _) => {
+                    matched.push(((Reverse(rule.priority), ix), &rule.icon, rule.count))
+                }
+                (None, Some(fallback)) => {
+                    matched.push(((Reverse(i32::MIN), usize::MAX), fallback, false))
+                }
+                (None, None) => (),
+            }
+        }
+        matched.sort_by_key(|(key, _, _)| *key);
+
+        let mut icons = String::new();
+        let mut rest = matched.as_slice();
+        while let Some(&(key, icon, count)) = rest.first() {
+            let n = rest.iter().take_while(|(k, _, _)| *k == key).count();
+            rest = &rest[n..];
+
+            if count && n > 1 {
+                icons.push_str(&format!("{icon}×{n}"));
+            } else if self.dedupe || count {
+                icons.push_str(icon);
+            } else {
+                icons.push_str(&icon.repeat(n));
+            }
+        }
+
+        icons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use penrose::Xid;
+
+    fn client(exe: &str, title: &str) -> ClientInfo {
+        ClientInfo {
+            id: Xid::from(1),
+            title: title.to_string(),
+            instance: String::new(),
+            class: String::new(),
+            role: None,
+            pid: None,
+            exe: Some(exe.to_string()),
+            cmdline: Vec::new(),
+            cwd: None,
+            floating: false,
+            urgent: false,
+        }
+    }
+
+    fn rule(icon: &str, exe: &str, priority: i32, count: bool) -> IconRule {
+        IconRule {
+            icon: icon.to_string(),
+            matcher: IconMatch::ExeContains(exe.to_string()),
+            priority,
+            count,
+        }
+    }
+
+    fn config(rules: Vec<IconRule>, dedupe: bool) -> IconConfig {
+        IconConfig {
+            rules,
+            fallback: None,
+            dedupe,
+        }
+    }
+
+    #[test]
+    fn icons_are_shown_by_priority_then_rule_order() {
+        let conf = config(
+            vec![
+                rule("a", "alpha", 0, false),
+                rule("b", "beta", 5, false),
+                rule("c", "gamma", 0, false),
+            ],
+            true,
+        );
+        let clients = [client("gamma", ""), client("alpha", ""), client("beta", "")];
+
+        assert_eq!(conf.icons(&clients), "bac");
+    }
+
+    #[test]
+    fn highest_priority_matching_rule_wins() {
+        let conf = config(
+            vec![
+                rule("t", "term", 0, false),
+                rule("v", "nvim", 1, false),
+                rule("x", "nvim", 1, false),
+            ],
+            true,
+        );
+
+        assert_eq!(conf.icons(&[client("nvim-term", "")]), "v");
+    }
+
+    #[test]
+    fn icons_are_repeated_unless_deduplicated() {
+        let rules = vec![rule("f", "firefox", 0, false)];
+        let clients = [client("firefox", ""), client("firefox", "")];
+
+        assert_eq!(config(rules.clone(), false).icons(&clients), "ff");
+        assert_eq!(config(rules, true).icons(&clients), "f");
+    }
+
+    #[test]
+    fn counted_icons_show_how_many_matched() {
+        let conf = config(vec![rule("t", "alacritty", 0, true)], false);
+        let three = [
+            client("alacritty", ""),
+            client("alacritty", ""),
+            client("alacritty", ""),
+        ];
+
+        assert_eq!(conf.icons(&three), "t×3");
+        assert_eq!(conf.icons(&three[..1]), "t");
+    }
+
+    #[test]
+    fn unmatched_clients_get_the_fallback_last() {
+        let mut conf = config(vec![rule("f", "firefox", -5, false)], true);
+        let clients = [client("xterm", ""), client("firefox", "")];
+        assert_eq!(conf.icons(&clients), "f");
+
+        conf.fallback = Some("?".to_string());
+        assert_eq!(conf.icons(&clients), "f?");
+    }
+
+    #[test]
+    fn rules_match_titles_and_classes() {
+        let mut calc = client("soffice.bin", "budget.ods - LibreOffice Calc");
+        calc.class = "libreoffice-calc".to_string();
+        let conf = |matcher| IconConfig {
+            rules: vec![IconRule {
+                icon: "c".to_string(),
+                matcher,
+                priority: 0,
+                count: false,
+            }],
+            ..config(Vec::new(), true)
+        };
+
+        assert_eq!(
+            conf(IconMatch::TitleContains("LibreOffice Calc".to_string())).icons([&calc]),
+            "c"
+        );
+        assert_eq!(
+            conf(IconMatch::Class("libreoffice-calc".to_string())).icons([&calc]),
+            "c"
+        );
+        assert_eq!(
+            conf(IconMatch::Exe("soffice".to_string())).icons([&calc]),
+            ""
+        );
+    }
+}
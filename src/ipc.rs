@@ -0,0 +1,319 @@
+use penrose::{core::State, pure::geometry::Rect, x::XConn, x11rb::RustConn, Error, Result, Xid};
+use penrose_bbarker_contrib::{
+    log::LogPenroseError, remote::RemoteSender, workspaces::workspace_clients, SYSTEM,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::mpsc::channel,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    bindings::Action,
+    events::{EventKind, Subscribers},
+};
+
+/// How long a client waits for the window manager to act on a request.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `$XDG_RUNTIME_DIR/dotpenrose-<display>.sock`, falling back to a socket in
+/// `/tmp` that is also named for the user, so that sessions don't collide.
+pub fn socket_path() -> PathBuf {
+    let display = env::var("DISPLAY")
+        .unwrap_or_default()
+        .trim_start_matches(':')
+        .replace('/', "_");
+    if let Ok(dir) = env::var("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join(format!("dotpenrose-{display}.sock"));
+    }
+
+    // SAFETY: getuid can't fail and has no preconditions
+    let uid = unsafe { libc::getuid() };
+
+    PathBuf::from("/tmp").join(format!("dotpenrose-{uid}-{display}.sock"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Request {
+    /// Run an action, e.g. `{"command": "spawn alacritty"}`
+    Command(Action),
+    /// Dump part of the window manager state, e.g. `{"query": "clients"}`
+    Query(Query),
+    /// Stream events of the given kinds (all if empty) as JSON lines until
+    /// the connection is closed, e.g. `{"subscribe": ["workspace_focused"]}`
+    Subscribe(Vec<EventKind>),
+}
+
+/// The requests answered with a single [Response], which are run on the
+/// window manager thread.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Call {
+    Command(Action),
+    Query(Query),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Query {
+    Workspaces,
+    Clients,
+    Screens,
+    Focused,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            result: None,
+        }
+    }
+
+    pub fn error(e: impl ToString) -> Self {
+        Self {
+            ok: false,
+            error: Some(e.to_string()),
+            result: None,
+        }
+    }
+
+    pub fn result(result: serde_json::Value) -> Self {
+        Self {
+            ok: true,
+            error: None,
+            result: Some(result),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClientState {
+    pub id: Xid,
+    pub tag: String,
+    pub title: String,
+    /// Executable name of the owning process
+    pub exe: String,
+    pub pid: Option<u32>,
+    pub floating: bool,
+    pub geometry: Option<Rect>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkspaceState {
+    pub tag: String,
+    pub layout: String,
+    /// The screen this workspace is visible on, if any
+    pub screen: Option<usize>,
+    pub focused: bool,
+    pub clients: Vec<Xid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScreenState {
+    pub index: usize,
+    pub tag: String,
+    pub geometry: Rect,
+    pub focused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FocusedState {
+    pub screen: usize,
+    pub tag: String,
+    pub client: Option<ClientState>,
+}
+
+/// Listen on [socket_path], running requests on the window manager thread
+/// via `remote` and sending events published to `subscribers`.
+///
+/// Clients write one JSON [Request] per line and get one JSON [Response] line
+/// back for each:
+///
+/// ```text
+/// {"command": "focus_tag 3"}
+/// {"ok":true}
+/// ```
+///
+/// Commands are written the same way as actions in the bindings file and
+/// queries return their data in `result`. See `dotpenrose-msg` for a command
+/// line client.
+pub fn serve(remote: RemoteSender<RustConn>, subscribers: Subscribers) -> Result<()> {
+    let path = socket_path();
+    // A socket left behind by a previous run would stop us binding
+    if path.exists() && UnixStream::connect(&path).is_err() {
+        fs::remove_file(&path)?;
+    }
+    // Only this user may send commands, even from a shared directory, so the
+    // socket is created without permissions for anyone else.
+    // SAFETY: umask can't fail and has no preconditions
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&path);
+    // SAFETY: as above
+    unsafe { libc::umask(umask) };
+    let listener = listener?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Some(stream) = stream.log_err("Couldn't accept IPC connection") else {
+                continue;
+            };
+            let remote = remote.clone();
+            let subscribers = subscribers.clone();
+            thread::spawn(move || {
+                handle_client(stream, &remote, &subscribers).log_err("Error handling IPC client");
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_client(
+    stream: UnixStream,
+    remote: &RemoteSender<RustConn>,
+    subscribers: &Subscribers,
+) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Subscribe(kinds)) => {
+                write_json(&mut writer, &Response::ok())?;
+                // Runs until the client hangs up and a write fails
+                for event in subscribers.subscribe(kinds) {
+                    write_json(&mut writer, &event)?;
+                }
+                return Ok(());
+            }
+            Ok(Request::Command(action)) => handle_call(Call::Command(action), remote),
+            Ok(Request::Query(query)) => handle_call(Call::Query(query), remote),
+            Err(e) => Response::error(format!("invalid request: {e}")),
+        };
+        write_json(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+fn write_json(writer: &mut impl Write, value: &impl Serialize) -> Result<()> {
+    let json = serde_json::to_string(value)
+        .map_err(|e| Error::Custom(format!("unable to serialize response: {e}")))?;
+
+    Ok(writeln!(writer, "{json}")?)
+}
+
+fn handle_call(call: Call, remote: &RemoteSender<RustConn>) -> Response {
+    let (tx, rx) = channel();
+
+    let sent = match call {
+        Call::Command(action) => remote.send(Box::new(move |state, x| {
+            let _ = tx.send(run_action(&action, state, x).map(|_| Response::ok()));
+            Ok(())
+        })),
+        Call::Query(query) => remote.send(Box::new(move |state, x| {
+            let _ = tx.send(run_query(query, state, x).map(Response::result));
+            Ok(())
+        })),
+    };
+
+    match sent.and_then(|_| {
+        rx.recv_timeout(REPLY_TIMEOUT)
+            .map_err(|_| Error::Custom("no reply from the window manager".to_string()))
+    }) {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) | Err(e) => Response::error(e),
+    }
+}
+
+fn run_action(action: &Action, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    action.validate(&state.client_set.ordered_tags())?;
+
+    action.key_handler().call(state, x)
+}
+
+fn run_query(query: Query, state: &State<RustConn>, x: &RustConn) -> Result<serde_json::Value> {
+    let cs = &state.client_set;
+    let current_tag = cs.current_tag();
+
+    let result = match query {
+        Query::Workspaces => serde_json::to_value(
+            cs.ordered_workspaces()
+                .map(|ws| WorkspaceState {
+                    tag: ws.tag().to_string(),
+                    layout: ws.layout_name(),
+                    screen: cs
+                        .screens()
+                        .find(|s| s.workspace.tag() == ws.tag())
+                        .map(|s| s.index()),
+                    focused: ws.tag() == current_tag,
+                    clients: ws.clients().copied().collect(),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Query::Clients => serde_json::to_value(client_states(state, x)),
+        Query::Screens => serde_json::to_value(
+            cs.screens()
+                .map(|s| ScreenState {
+                    index: s.index(),
+                    tag: s.workspace.tag().to_string(),
+                    geometry: s.geometry(),
+                    focused: s.index() == cs.current_screen().index(),
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Query::Focused => serde_json::to_value(FocusedState {
+            screen: cs.current_screen().index(),
+            tag: current_tag.to_string(),
+            client: cs.current_client().and_then(|&id| {
+                client_states(state, x)
+                    .into_iter()
+                    .find(|client| client.id == id)
+            }),
+        }),
+    };
+
+    result.map_err(|e| Error::Custom(format!("unable to serialize {query:?}: {e}")))
+}
+
+/// Every managed client, in workspace order.
+pub fn client_states<X: XConn>(state: &State<X>, x: &X) -> Vec<ClientState> {
+    let cs = &state.client_set;
+
+    cs.ordered_workspaces()
+        .flat_map(|ws| {
+            let info = workspace_clients(&SYSTEM, state, x, ws);
+            info.clients
+                .into_iter()
+                .map(|client| ClientState {
+                    id: client.id,
+                    tag: info.tag.clone(),
+                    title: client.title,
+                    exe: client.exe.unwrap_or_default(),
+                    pid: client.pid,
+                    floating: client.floating,
+                    geometry: x.client_geometry(client.id).ok(),
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
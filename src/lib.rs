@@ -2,20 +2,28 @@
 #![warn(future_incompatible, rust_2024_compatibility)]
 
 use once_cell::sync::Lazy;
+use penrose::{
+    builtin::layout::{transformers::ReserveTop, MainAndStack},
+    core::layout::LayoutStack,
+    stack,
+};
 use std::ops::RangeInclusive;
 // #![deny(unused_crate_dependencies)]
 pub mod bar;
+pub mod bindings;
+pub mod config;
+pub mod events;
+pub mod icons;
+pub mod ipc;
+pub mod reload;
+pub mod schedule;
+pub mod scratchpad;
+pub mod screens;
+pub mod session;
+pub mod theme;
 
 pub const FONT: &str = "Hasklug Nerd Font Mono";
 
-// Kanagawa
-// https://github.com/rebelot/kanagawa.nvim?tab=readme-ov-file#color-palette
-pub const BLACK: u32 = 0x252535ff; // #252535
-pub const WHITE: u32 = 0xdcd7baff; // #dcd7ba
-pub const GREY: u32 = 0x363646ff; //  #363646
-pub const BLUE: u32 = 0x658594ff; //  #658594
-pub const RED: u32 = 0xc34043ff; //   #C34043
-
 // Let's start with 29 tags
 
 pub const NUM_FAST_ACCESS_WORKSPACES: u16 = 9;
@@ -23,3 +31,8 @@ pub const NUM_WORKSPACES: u16 = NUM_FAST_ACCESS_WORKSPACES + 20;
 pub const WORKSPACES: RangeInclusive<u16> = 1..=(NUM_WORKSPACES);
 pub static ALL_TAGS: Lazy<Vec<String>> =
     Lazy::new(|| WORKSPACES.map(|ix| ix.to_string()).collect());
+
+pub fn layouts(bar_height: u32) -> LayoutStack {
+    let stack = MainAndStack::side(1, 0.5, 0.1);
+    stack!(stack).map(|layout| ReserveTop::wrap(layout, bar_height))
+}
@@ -1,103 +1,43 @@
 #![allow(clippy::unit_arg)]
 
-use nunny::NonEmpty;
-use once_cell::sync::Lazy;
 use penrose::{
-    builtin::{
-        actions::{
-            exit,
-            floating::{MouseDragHandler, MouseResizeHandler},
-            modify_with, send_layout_message, spawn,
-        },
-        layout::{
-            messages::{ExpandMain, IncMain, ShrinkMain},
-            transformers::ReserveTop,
-            MainAndStack,
-        },
-    },
+    builtin::actions::floating::{MouseDragHandler, MouseResizeHandler},
     core::{
-        bindings::{
-            parse_keybindings_with_xmodmap, KeyEventHandler, ModifierKey, MouseButton,
-            MouseEventHandler, MouseState,
-        },
-        hooks::StateHook,
-        layout::LayoutStack,
-        Config, WindowManager,
+        bindings::{ModifierKey, MouseButton, MouseEventHandler, MouseState},
+        Config, State, WindowManager,
     },
-    extensions::hooks::{add_ewmh_hooks, SpawnOnStartup},
-    map, stack,
+    extensions::hooks::add_ewmh_hooks,
+    map,
     x11rb::RustConn,
-    Result,
+    Error, Result,
 };
 use penrose_bbarker_contrib::{
-    is_in_path, is_running,
-    menus::finder::{
-        goto_workspace_by_apps, send_to_workspace_menu, workspace_menu, GOTO_WS_CONFIG,
-    },
-    KeyHandler,
+    log::LogPenroseError,
+    menus::{backend::FZF_POPUP_CLASS, finder::SelectedMenu},
+    process_cache::add_process_cache_hooks,
+    remote::remote_channel,
+    rules::apply_window_rules,
+    services::{restarted_in_session, start_services},
+    session::add_session_hooks,
+    swallow::{add_swallow_hooks, swallow},
 };
 
 use std::collections::HashMap;
 use tracing_subscriber::util::SubscriberInitExt;
 
 use dotpenrose::{
-    bar::{status_bar, BAR_HEIGHT_PX_PRIMARY},
-    ALL_TAGS, NUM_FAST_ACCESS_WORKSPACES,
+    bar::status_bar,
+    bindings::Bindings,
+    config::UserConfig,
+    events::{add_event_hooks, Subscribers},
+    ipc, layouts,
+    reload::{reload_on_signal, AppliedTheme},
+    schedule::{theme_at, watch_color_schedule},
+    scratchpad::add_scratchpads,
+    screens::{apply_configured_outputs, screen_change_hook, BarScreens},
+    session::exit_on_signal,
 };
 
-static GOTO_WS: Lazy<Box<dyn Fn() -> KeyHandler + Send + Sync>> =
-    Lazy::new(|| goto_workspace_by_apps(&GOTO_WS_CONFIG));
-
-fn raw_key_bindings() -> HashMap<String, Box<dyn KeyEventHandler<RustConn>>> {
-    let action_bindings = map! {
-        map_keys: |k: &str| k.to_string();
-        "M-f" => GOTO_WS(),
-        "M-g" => workspace_menu(),
-        "M-S-g" => send_to_workspace_menu(),
-        "M-Left" => modify_with(|cs| cs.focus_previous_workspace()),
-        "M-Right" => modify_with(|cs| cs.focus_next_workspace()),
-        "M-n" => modify_with(|cs| cs.focus_down()),
-        "M-a" => modify_with(|cs| cs.focus_up()),
-        "M-S-n" => modify_with(|cs| cs.swap_down()),
-        "M-S-a" => modify_with(|cs| cs.swap_up()),
-        "M-S-c" => modify_with(|cs| cs.kill_focused()),
-        "M-Tab" => modify_with(|cs| cs.toggle_tag()),
-        "M-m" => modify_with(|cs| cs.next_screen()),
-        "M-i" => modify_with(|cs| cs.previous_screen()),
-        "M-s" => modify_with(|cs| cs.drag_workspace_forward()),
-        "M-S-s" => modify_with(|cs| cs.drag_workspace_backward()),
-        "M-space" => modify_with(|cs| cs.next_layout()),
-        "M-S-space" => modify_with(|cs| cs.previous_layout()),
-        "M-S-Up" => send_layout_message(|| IncMain(1)),
-        "M-S-Down" => send_layout_message(|| IncMain(-1)),
-        "M-l" => send_layout_message(|| ExpandMain),
-        "M-h" => send_layout_message(|| ShrinkMain),
-        "M-Return" => modify_with(|cs| cs.swap_focus_and_head()),
-        "M-p" => spawn("dmenu_run"),
-        // "M-p" => spawn("yeganesh -x"), // not working for some reason
-        "M-S-z" => spawn("xscreensaver-command -lock"),
-        "M-S-Return" => spawn("alacritty"),
-        "M-A-Escape" => exit(),
-    };
-    (1..=NUM_FAST_ACCESS_WORKSPACES)
-        .map(|ws| ws.to_string())
-        .flat_map(|tag| {
-            let tag_copy = tag.clone();
-            [
-                (
-                    format!("M-{tag_copy}"),
-                    modify_with(move |client_set| client_set.focus_tag(tag_copy.clone())),
-                ),
-                (
-                    format!("M-S-{}", tag.clone()),
-                    modify_with(move |client_set| client_set.move_focused_to_tag(tag.clone())),
-                ),
-            ]
-        })
-        .chain(action_bindings)
-        .collect::<HashMap<String, Box<dyn KeyEventHandler<RustConn>>>>()
-}
-
 fn mouse_bindings() -> HashMap<MouseState, Box<dyn MouseEventHandler<RustConn>>> {
     map! {
         map_keys: |(button, modifiers)| MouseState { button, modifiers };
@@ -107,48 +47,66 @@ fn mouse_bindings() -> HashMap<MouseState, Box<dyn MouseEventHandler<RustConn>>>
     }
 }
 
-fn layout() -> LayoutStack {
-    let stack = MainAndStack::side(1, 0.5, 0.1);
-    stack!(stack).map(|layout| ReserveTop::wrap(layout, BAR_HEIGHT_PX_PRIMARY))
-}
-
 fn main() -> Result<()> {
     tracing_subscriber::fmt()
         .with_env_filter("info")
         .finish()
         .init();
 
-    let startup_progs: NonEmpty<[(&str, &str); 2]> = nunny::array![
-        ("xscreensaver", ""),
-        ("nvidia-settings", "--load-config-only"),
-    ];
-
-    let progs_to_start: Vec<(&str, &str)> = startup_progs
-        .into_iter()
-        .filter(|(prog, _)| is_in_path(prog) && !is_running(prog))
-        .collect();
-    let startup_hook = progs_to_start
-        .into_iter()
-        .map(
-            |(prog, args)| SpawnOnStartup::boxed(format!("{prog} {args}").trim().to_owned()), // .reduce(|ha, hi| ha.then(hi))
-        )
-        .collect::<Vec<_>>();
-
     let conn = RustConn::new()?;
-    let key_bindings = parse_keybindings_with_xmodmap(raw_key_bindings())?;
-    let config = add_ewmh_hooks(Config {
-        default_layouts: layout(),
-        tags: ALL_TAGS.clone(),
-        startup_hook: Some(StateHook::boxed(startup_hook)),
+    let user_config = UserConfig::load();
+    // Before the window manager looks for screens
+    apply_configured_outputs(&user_config, &conn).log_err("Couldn't apply the output layout");
+    let bindings = Bindings::try_new(user_config.key_bindings(), user_config.tags.clone())?;
+    let (remote, remote_receiver) = remote_channel()?;
+    let subscribers = Subscribers::default();
+    let theme = theme_at(&user_config, chrono::Local::now().naive_local());
+    let config = add_session_hooks(add_ewmh_hooks(Config {
+        default_layouts: layouts(user_config.bar.height_primary),
+        tags: user_config.tags.clone(),
+        normal_border: theme.border_normal,
+        focused_border: theme.border_focused,
+        floating_classes: vec![
+            "dmenu".to_string(),
+            "dunst".to_string(),
+            FZF_POPUP_CLASS.to_string(),
+        ],
         ..Default::default()
+    }));
+    let config = add_swallow_hooks(add_process_cache_hooks(config));
+    let mut config = add_event_hooks(config, subscribers.clone());
+    // Rules are read from the config extension so that they follow reloads.
+    // Swallowing runs after the rules, so a swallowed terminal's tag wins.
+    config.compose_or_set_manage_hook(|id, state: &mut State<RustConn>, x: &RustConn| {
+        let swallow_config = state.extension::<UserConfig>()?.borrow().swallow.clone();
+        swallow(&swallow_config, id, state, x)
     });
+    config.compose_or_set_manage_hook(|id, state: &mut State<RustConn>, x: &RustConn| {
+        let rules = state.extension::<UserConfig>()?.borrow().rules.clone();
+        apply_window_rules(&rules, id, state, x)
+    });
+    config.compose_or_set_event_hook(screen_change_hook(remote.clone()));
+    config.compose_or_set_event_hook(remote_receiver.event_hook());
+    reload_on_signal(remote.clone())?;
+    exit_on_signal(remote.clone())?;
+    watch_color_schedule(remote.clone());
+    ipc::serve(remote.clone(), subscribers).log_err("Couldn't start the IPC socket");
+    let restarted = restarted_in_session(&conn).unwrap_or(false);
+    start_services(user_config.services.clone(), restarted).log_err("Couldn't start services");
+
+    let screens = BarScreens::current(&conn)?;
+    let bar = status_bar(&user_config, theme, &screens)
+        .map_err(|e| Error::Custom(format!("unable to create status bar: {e}")))?;
 
-    let bar = status_bar().unwrap(); // FIXME: properaly convert error
-    let wm = bar.add_to(WindowManager::new(
-        config,
-        key_bindings,
-        mouse_bindings(),
-        conn,
-    )?);
+    // Key bindings are dispatched by `Bindings` so that they can be reloaded
+    let wm = WindowManager::new(config, HashMap::new(), mouse_bindings(), conn)?;
+    let mut wm = add_scratchpads(wm, &user_config.scratchpads);
+    wm.add_extension(SelectedMenu(user_config.menu.backend()));
+    wm.add_extension(user_config);
+    // Lets menus be shown without blocking the window manager
+    wm.add_extension(remote);
+    wm.add_extension(AppliedTheme(theme));
+    wm.add_extension(screens);
+    let wm = bar.add_to(bindings.add_to(wm));
     wm.run()
 }
@@ -0,0 +1,119 @@
+use penrose::{
+    core::State,
+    x::{XConn, XConnExt},
+    x11rb::RustConn,
+    Error, Result,
+};
+use penrose_bbarker_contrib::{
+    log::LogPenroseError, menus::finder::SelectedMenu, remote::RemoteSender,
+};
+use signal_hook::{consts::SIGUSR1, iterator::Signals};
+use std::thread;
+
+use crate::{
+    bar::status_bar,
+    bindings::replace_bindings,
+    config::UserConfig,
+    layouts,
+    schedule::theme_at,
+    screens::{apply_configured_outputs, BarScreens},
+    theme::Theme,
+};
+
+/// The theme currently used by the bar and window borders.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AppliedTheme(pub Theme);
+
+/// Re-read the config file and apply it to the running window manager.
+///
+/// Clients stay where they are: workspaces are added or removed to match the
+/// configured tags, with clients on removed workspaces moved to the first tag.
+/// A config file that can't be loaded leaves the running config as it is.
+pub fn reload_config(state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    let config = UserConfig::try_load()?;
+
+    apply_configured_outputs(&config, x).log_err("Couldn't apply the output layout");
+    sync_tags(&config, state);
+    replace_bindings(config.key_bindings(), config.tags.clone(), state, x)?;
+    apply_style(&config, state, x)?;
+    state.add_extension(SelectedMenu(config.menu.backend()));
+    state.add_extension(config);
+
+    Ok(())
+}
+
+/// Rebuild the bar and recolor window borders using the theme that `config`
+/// gives for the current time.
+pub fn apply_style(config: &UserConfig, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    let theme = theme_at(config, chrono::Local::now().naive_local());
+    state.config.normal_border = theme.border_normal;
+    state.config.focused_border = theme.border_focused;
+
+    // Replacing the extension drops the old bar, closing its X connection
+    // and with it the old bar windows.
+    let screens = BarScreens::current(x)?;
+    let bar = status_bar::<RustConn>(config, theme, &screens)
+        .map_err(|e| Error::Custom(format!("unable to rebuild status bar: {e}")))?;
+    state.add_extension(bar);
+    penrose_ui::bar::startup_hook(state, x)?;
+    state.add_extension(screens);
+    state.add_extension(AppliedTheme(theme));
+
+    // Make sure the new border colors are applied to every client
+    for &client in state.client_set.clients() {
+        x.set_client_border_color(client, state.config.normal_border)?;
+    }
+    x.refresh(state)
+}
+
+fn sync_tags<X: XConn>(config: &UserConfig, state: &mut State<X>) {
+    let cs = &mut state.client_set;
+    let bar_height = config.bar.height_primary;
+
+    for tag in config.tags.iter() {
+        if !cs.contains_tag(tag) {
+            cs.add_workspace(tag.clone(), layouts(bar_height))
+                .log_err(&format!("Couldn't add workspace {tag}"));
+        }
+    }
+
+    let fallback = &config.tags[0];
+    let removed: Vec<String> = cs
+        .ordered_tags()
+        .into_iter()
+        .filter(|tag| !config.tags.contains(tag))
+        .collect();
+    for tag in removed {
+        let clients: Vec<_> = cs
+            .workspace(&tag)
+            .map(|ws| ws.clients().copied().collect())
+            .unwrap_or_default();
+        for client in clients.iter() {
+            cs.move_client_to_tag(client, fallback);
+        }
+        cs.remove_workspace(&tag)
+            .log_err(&format!("Couldn't remove workspace {tag}"));
+    }
+
+    // Pick up any change to the bar height, keeping the current layout
+    for ws in cs.workspaces_mut() {
+        let name = ws.layout_name();
+        ws.set_available_layouts(layouts(bar_height));
+        ws.set_layout_by_name(&name);
+    }
+}
+
+/// Reload the config whenever we receive SIGUSR1 (`pkill -USR1 dotpenrose`).
+pub fn reload_on_signal(remote: RemoteSender<RustConn>) -> Result<()> {
+    let mut signals = Signals::new([SIGUSR1])?;
+
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            remote
+                .send(Box::new(reload_config))
+                .log_err("Couldn't request a config reload");
+        }
+    });
+
+    Ok(())
+}
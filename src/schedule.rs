@@ -0,0 +1,210 @@
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use penrose::{core::State, x11rb::RustConn, Error, Result};
+use penrose_bbarker_contrib::{log::LogPenroseError, remote::RemoteSender};
+use serde::{Deserialize, Deserializer};
+use std::{thread, time::Duration};
+
+use crate::{
+    config::UserConfig,
+    reload::{apply_style, AppliedTheme},
+    theme::{ColorOverrides, Theme},
+};
+
+/// How often we check whether a different schedule has become active.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A time of day written as "HH:MM" in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TimeOfDay(NaiveTime);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        NaiveTime::parse_from_str(&s, "%H:%M")
+            .map(TimeOfDay)
+            .map_err(|e| Error::Custom(format!("invalid time '{s}', expected HH:MM: {e}")))
+    }
+}
+
+/// Days are written as names ("mon", "Tuesday"...) or as "weekdays" / "weekends".
+fn days<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<Weekday>, D::Error> {
+    use Weekday::*;
+
+    let names = Vec::<String>::deserialize(deserializer)?;
+    let mut days = Vec::new();
+    for name in names {
+        match name.to_lowercase().as_str() {
+            "weekdays" => days.extend([Mon, Tue, Wed, Thu, Fri]),
+            "weekends" => days.extend([Sat, Sun]),
+            day => days.push(
+                day.parse()
+                    .map_err(|_| serde::de::Error::custom(format!("invalid day '{name}'")))?,
+            ),
+        }
+    }
+
+    Ok(days)
+}
+
+/// A time window during which the bar and borders use different colors, e.g.
+/// a red highlight after 22:00 as a reminder to stop for the day.
+///
+/// `from` defaults to the start of the day and `to` to the end of it. A window
+/// with `from` after `to` runs over midnight. If `days` is given the window
+/// only applies on those days.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColorSchedule {
+    pub from: Option<TimeOfDay>,
+    pub to: Option<TimeOfDay>,
+    #[serde(default, deserialize_with = "days")]
+    pub days: Vec<Weekday>,
+    pub colors: ColorOverrides,
+}
+
+impl ColorSchedule {
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        let t = now.time();
+        let in_window = match (self.from, self.to) {
+            (None, None) => true,
+            (Some(from), None) => t >= from.0,
+            (None, Some(to)) => t < to.0,
+            (Some(from), Some(to)) if from <= to => t >= from.0 && t < to.0,
+            (Some(from), Some(to)) => t >= from.0 || t < to.0,
+        };
+
+        in_window && (self.days.is_empty() || self.days.contains(&now.weekday()))
+    }
+}
+
+/// The theme to use at the given time: the first active schedule wins.
+pub fn theme_at(config: &UserConfig, now: NaiveDateTime) -> Theme {
+    let theme = config.theme();
+
+    config
+        .color_schedule
+        .iter()
+        .find(|schedule| schedule.is_active(now))
+        .map_or(theme, |schedule| schedule.colors.apply_to(theme))
+}
+
+/// Restyle the bar and borders if a different schedule has become active.
+pub fn apply_color_schedule(state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    let config = state.extension::<UserConfig>()?.borrow().clone();
+    let theme = theme_at(&config, chrono::Local::now().naive_local());
+    let applied = state.extension::<AppliedTheme>()?.borrow().0;
+
+    if theme == applied {
+        return Ok(());
+    }
+
+    apply_style(&config, state, x)
+}
+
+/// Periodically check the color schedule from a background thread.
+pub fn watch_color_schedule(remote: RemoteSender<RustConn>) {
+    thread::spawn(move || loop {
+        thread::sleep(CHECK_INTERVAL);
+        if remote
+            .send(Box::new(apply_color_schedule))
+            .log_err("Couldn't check the color schedule")
+            .is_none()
+        {
+            break;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    // 2024-06-01 was a Saturday
+    fn at(day: u32, hour: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day)
+            .unwrap()
+            .and_hms_opt(hour, min, 0)
+            .unwrap()
+    }
+
+    fn schedule(json: &str) -> ColorSchedule {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn window_over_midnight_on_weekends() {
+        let late = schedule(
+            r##"{ "from": "22:00", "to": "06:00", "days": ["weekends"],
+                  "colors": { "highlight": "#ff0000" } }"##,
+        );
+
+        assert!(late.is_active(at(1, 23, 30)));
+        assert!(late.is_active(at(2, 1, 0)));
+        assert!(late.is_active(at(2, 22, 0)));
+        assert!(!late.is_active(at(2, 6, 0)));
+        assert!(!late.is_active(at(1, 21, 59)));
+        // Days are calendar days, so early on Monday is a weekday
+        assert!(!late.is_active(at(3, 23, 30)));
+        assert!(!late.is_active(at(3, 1, 0)));
+    }
+
+    #[test]
+    fn window_within_a_day() {
+        let work =
+            schedule(r#"{ "from": "09:00", "to": "17:30", "days": ["weekdays"], "colors": {} }"#);
+
+        assert!(work.is_active(at(3, 9, 0)));
+        assert!(work.is_active(at(3, 17, 29)));
+        assert!(!work.is_active(at(3, 17, 30)));
+        assert!(!work.is_active(at(3, 8, 59)));
+        assert!(!work.is_active(at(1, 12, 0)));
+    }
+
+    #[test]
+    fn open_ended_windows_and_day_names() {
+        let evening = schedule(r#"{ "from": "18:00", "days": ["fri", "Saturday"], "colors": {} }"#);
+        let morning = schedule(r#"{ "to": "08:00", "colors": {} }"#);
+
+        assert!(evening.is_active(at(1, 23, 59)));
+        assert!(!evening.is_active(at(1, 17, 59)));
+        assert!(!evening.is_active(at(2, 19, 0)));
+        assert!(morning.is_active(at(4, 0, 0)));
+        assert!(!morning.is_active(at(4, 8, 0)));
+    }
+
+    #[test]
+    fn invalid_times_and_days_are_rejected() {
+        for json in [
+            r#"{ "from": "25:00", "colors": {} }"#,
+            r#"{ "from": "9am", "colors": {} }"#,
+            r#"{ "days": ["someday"], "colors": {} }"#,
+        ] {
+            assert!(serde_json::from_str::<ColorSchedule>(json).is_err());
+        }
+    }
+
+    #[test]
+    fn first_active_schedule_wins() {
+        let config = UserConfig {
+            color_schedule: vec![
+                schedule(r##"{ "from": "22:00", "colors": { "highlight": "#ff0000" } }"##),
+                schedule(r##"{ "from": "20:00", "colors": { "highlight": "#00ff00" } }"##),
+            ],
+            ..Default::default()
+        };
+        let theme = config.theme();
+
+        assert_eq!(theme_at(&config, at(1, 12, 0)), theme);
+        assert_eq!(
+            theme_at(&config, at(1, 21, 0)).highlight,
+            config.color_schedule[1].colors.highlight.unwrap()
+        );
+        assert_eq!(
+            theme_at(&config, at(1, 23, 0)).highlight,
+            config.color_schedule[0].colors.highlight.unwrap()
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use penrose::{
+    core::{bindings::KeyEventHandler, State, WindowManager},
+    extensions::hooks::{
+        manage::FloatingCentered,
+        named_scratchpads::{add_named_scratchpads, NamedScratchPad, ToggleNamedScratchPad},
+    },
+    x::XConn,
+    x11rb::RustConn,
+    Error, Result,
+};
+use penrose_bbarker_contrib::rules::WindowMatch;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A program that is shown and hidden with a key as a floating window centered
+/// on the focused screen. It is started the first time it is toggled and kept
+/// on a hidden workspace rather than any of the configured tags.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scratchpad {
+    pub name: String,
+    pub command: String,
+    /// How to recognise the window once `command` has started
+    #[serde(flatten)]
+    pub matcher: WindowMatch,
+    /// Fraction of the screen's width
+    #[serde(default = "default_size")]
+    pub width: f64,
+    /// Fraction of the screen's height
+    #[serde(default = "default_size")]
+    pub height: f64,
+}
+
+fn default_size() -> f64 {
+    0.6
+}
+
+/// Only a terminal with a class of its own, so that windows opened the usual
+/// way are never taken for a scratchpad.
+pub fn default_scratchpads() -> Vec<Scratchpad> {
+    vec![Scratchpad {
+        name: "terminal".to_string(),
+        command: "alacritty --class scratchpad-terminal".to_string(),
+        matcher: WindowMatch::Class("scratchpad-terminal".to_string()),
+        width: default_size(),
+        height: default_size(),
+    }]
+}
+
+impl Scratchpad {
+    pub fn validate(&self) -> Result<()> {
+        let in_range = |f: f64| f > 0.0 && f <= 1.0;
+
+        if in_range(self.width) && in_range(self.height) {
+            Ok(())
+        } else {
+            Err(Error::Custom(format!(
+                "scratchpad '{}' needs a width and height between 0 and 1",
+                self.name
+            )))
+        }
+    }
+}
+
+/// The toggles for each registered scratchpad, by name.
+struct ScratchpadToggles(BTreeMap<String, ToggleNamedScratchPad>);
+
+/// Register `scratchpads` with the window manager. These are fixed for the
+/// life of the window manager: changes to them need a restart to take effect.
+pub fn add_scratchpads<X>(wm: WindowManager<X>, scratchpads: &[Scratchpad]) -> WindowManager<X>
+where
+    X: XConn + 'static,
+{
+    let (nsps, toggles): (Vec<_>, BTreeMap<_, _>) = scratchpads
+        .iter()
+        .map(|sp| {
+            let (nsp, toggle) = NamedScratchPad::new(
+                sp.name.clone(),
+                sp.command.clone(),
+                sp.matcher.clone(),
+                FloatingCentered::new(sp.width, sp.height),
+                // Re-center on whichever screen is focused each time it's shown
+                true,
+            );
+            (nsp, (sp.name.clone(), toggle))
+        })
+        .unzip();
+
+    let mut wm = add_named_scratchpads(wm, nsps);
+    wm.state.add_extension(ScratchpadToggles(toggles));
+
+    wm
+}
+
+/// Show the named scratchpad on the focused screen, starting it if needed, or
+/// hide it if it's already showing.
+pub fn toggle_scratchpad(name: &str, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    let toggle = state
+        .extension::<ScratchpadToggles>()?
+        .borrow()
+        .0
+        .get(name)
+        .cloned();
+
+    match toggle {
+        Some(mut toggle) => toggle.call(state, x),
+        None => Err(Error::Custom(format!("unknown scratchpad '{name}'"))),
+    }
+}
@@ -0,0 +1,84 @@
+use penrose::{
+    core::State,
+    pure::geometry::Rect,
+    x::{XConn, XEvent},
+    x11rb::RustConn,
+    Result,
+};
+use penrose_bbarker_contrib::{
+    log::LogPenroseError,
+    outputs::{apply_outputs, primary_position},
+    remote::RemoteSender,
+};
+
+use crate::{config::UserConfig, reload::apply_style};
+
+/// The screens that the bar is built for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BarScreens {
+    pub rects: Vec<Rect>,
+    /// Index into `rects` of the screen that gets the full size bar
+    pub primary: usize,
+}
+
+impl BarScreens {
+    /// The screens as the bar will find them, with the RandR primary output
+    /// as the primary screen, or the first screen if there isn't one.
+    pub fn current(x: &RustConn) -> Result<Self> {
+        let rects = x.screen_details()?;
+        let primary = primary_position(x.connection(), *x.root())
+            .log_err("Couldn't find the primary output")
+            .flatten()
+            .and_then(|(px, py)| rects.iter().position(|r| (r.x, r.y) == (px, py)))
+            .unwrap_or(0);
+
+        Ok(Self { rects, primary })
+    }
+}
+
+/// Set up monitors as described by the `outputs` in `config`.
+pub fn apply_configured_outputs(config: &UserConfig, x: &RustConn) -> Result<bool> {
+    apply_outputs(x.connection(), *x.root(), &config.outputs)
+}
+
+/// When a monitor is plugged in or removed, re-apply the configured layout and
+/// then rebuild the bar for the new set of screens.
+///
+/// Penrose picks up the new screens itself once this hook returns, so the
+/// rebuild is queued up through `remote` to run after that.
+pub fn screen_change_hook(
+    remote: RemoteSender<RustConn>,
+) -> impl FnMut(&XEvent, &mut State<RustConn>, &RustConn) -> Result<bool> {
+    move |event, state, x| {
+        let screens_changed = match event {
+            XEvent::RandrNotify => true,
+            XEvent::ConfigureNotify(e) => e.is_root,
+            _ => false,
+        };
+        if screens_changed {
+            let config = state.extension::<UserConfig>()?.borrow().clone();
+            // This causes more RandR events, but by the time those arrive
+            // there is nothing left to change
+            apply_configured_outputs(&config, x).log_err("Couldn't apply the output layout");
+            remote
+                .send(Box::new(rebuild_bar_for_screens))
+                .log_err("Couldn't queue a bar rebuild");
+        }
+
+        Ok(true)
+    }
+}
+
+/// Rebuild the bar if the screens have changed since it was last built.
+fn rebuild_bar_for_screens(state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    let screens = BarScreens::current(x)?;
+    let unchanged = state
+        .extension::<BarScreens>()
+        .is_ok_and(|built_for| *built_for.borrow() == screens);
+    if unchanged {
+        return Ok(());
+    }
+
+    let config = state.extension::<UserConfig>()?.borrow().clone();
+    apply_style(&config, state, x)
+}
@@ -0,0 +1,29 @@
+use penrose::{builtin::actions::exit, core::State, x11rb::RustConn, Result};
+use penrose_bbarker_contrib::{log::LogPenroseError, remote::RemoteSender, session::save_session};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use std::thread;
+
+/// Save the session so that `run_penrose.sh` can restore it, then exit.
+pub fn save_and_exit(state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    save_session(state, x);
+
+    exit().call(state, x)
+}
+
+/// Save the session and exit cleanly when we are asked to terminate.
+pub fn exit_on_signal(remote: RemoteSender<RustConn>) -> Result<()> {
+    let mut signals = Signals::new([SIGTERM, SIGINT, SIGHUP])?;
+
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            remote
+                .send(Box::new(save_and_exit))
+                .log_err("Couldn't request a clean exit");
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use penrose::{core::State, x11rb::RustConn, Color, Error, Result};
+use serde::{Deserialize, Deserializer};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{
+    config::{config_path, UserConfig},
+    reload::apply_style,
+};
+
+pub const DEFAULT_THEME: &str = "kanagawa";
+
+/// Colors are written as "#RRGGBB" or "#RRGGBBAA" in the config file.
+pub(crate) fn hex_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Color, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Color::try_from(s.as_str()).map_err(serde::de::Error::custom)
+}
+
+fn opt_hex_color<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<Color>, D::Error> {
+    hex_color(deserializer).map(Some)
+}
+
+/// The colors used by the bar and window borders, by role.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    #[serde(deserialize_with = "hex_color")]
+    pub bg: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub fg: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub highlight: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub inactive: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub urgent: Color,
+    /// Optional in theme files, which predate it
+    #[serde(deserialize_with = "hex_color", default = "default_warning")]
+    pub warning: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub border_normal: Color,
+    #[serde(deserialize_with = "hex_color")]
+    pub border_focused: Color,
+}
+
+impl Theme {
+    /// https://github.com/rebelot/kanagawa.nvim?tab=readme-ov-file#color-palette
+    ///
+    /// The borders keep penrose's default colors, which were used before
+    /// themes existed.
+    pub fn kanagawa() -> Self {
+        Self {
+            bg: 0x252535ff.into(),             // #252535
+            fg: 0xdcd7baff.into(),             // #dcd7ba
+            highlight: 0x658594ff.into(),      // #658594
+            inactive: 0x363646ff.into(),       // #363646
+            urgent: 0xc34043ff.into(),         // #c34043
+            warning: 0xe6c384ff.into(),        // #e6c384
+            border_normal: 0x3c3836ff.into(),  // #3c3836
+            border_focused: 0xcc241dff.into(), // #cc241d
+        }
+    }
+
+    /// Matches `.config/helix/themes/gruvbox.toml`
+    pub fn gruvbox() -> Self {
+        Self {
+            bg: 0x282828ff.into(),             // #282828
+            fg: 0xebdbb2ff.into(),             // #ebdbb2
+            highlight: 0x458588ff.into(),      // #458588
+            inactive: 0x504945ff.into(),       // #504945
+            urgent: 0xfb4934ff.into(),         // #fb4934
+            warning: 0xfabd2fff.into(),        // #fabd2f
+            border_normal: 0x3c3836ff.into(),  // #3c3836
+            border_focused: 0xcc241dff.into(), // #cc241d
+        }
+    }
+}
+
+fn default_warning() -> Color {
+    Theme::kanagawa().warning
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::kanagawa()
+    }
+}
+
+/// Individual colors that replace those of a [Theme].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorOverrides {
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub bg: Option<Color>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub fg: Option<Color>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub highlight: Option<Color>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub inactive: Option<Color>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub urgent: Option<Color>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub warning: Option<Color>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub border_normal: Option<Color>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub border_focused: Option<Color>,
+}
+
+impl ColorOverrides {
+    pub fn apply_to(&self, theme: Theme) -> Theme {
+        Theme {
+            bg: self.bg.unwrap_or(theme.bg),
+            fg: self.fg.unwrap_or(theme.fg),
+            highlight: self.highlight.unwrap_or(theme.highlight),
+            inactive: self.inactive.unwrap_or(theme.inactive),
+            urgent: self.urgent.unwrap_or(theme.urgent),
+            warning: self.warning.unwrap_or(theme.warning),
+            border_normal: self.border_normal.unwrap_or(theme.border_normal),
+            border_focused: self.border_focused.unwrap_or(theme.border_focused),
+        }
+    }
+}
+
+/// `themes/` next to the config file: each `<name>.toml` in here gives every
+/// role of a [Theme] and can be selected by name.
+pub fn themes_dir() -> Option<PathBuf> {
+    config_path().and_then(|path| path.parent().map(|dir| dir.join("themes")))
+}
+
+pub fn load_theme_file(path: &PathBuf) -> Result<Theme> {
+    let contents = fs::read_to_string(path)?;
+
+    toml::from_str(&contents)
+        .map_err(|e| Error::Custom(format!("invalid theme {}: {e}", path.display())))
+}
+
+/// The bundled themes along with any found in [themes_dir], which take
+/// precedence when the names clash.
+pub fn available_themes() -> BTreeMap<String, Theme> {
+    let mut themes = BTreeMap::from([
+        ("kanagawa".to_string(), Theme::kanagawa()),
+        ("gruvbox".to_string(), Theme::gruvbox()),
+    ]);
+
+    let files = themes_dir()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"));
+    for path in files {
+        // Broken theme files are reported when they are selected
+        if let (Some(name), Ok(theme)) = (path.file_stem(), load_theme_file(&path)) {
+            themes.insert(name.to_string_lossy().into_owned(), theme);
+        }
+    }
+
+    themes
+}
+
+pub fn find_theme(name: &str) -> Result<Theme> {
+    if let Some(path) = themes_dir().map(|dir| dir.join(format!("{name}.toml"))) {
+        if path.exists() {
+            return load_theme_file(&path);
+        }
+    }
+
+    available_themes()
+        .remove(name)
+        .ok_or_else(|| Error::Custom(format!("unknown theme '{name}'")))
+}
+
+/// Switch to the named theme until the config is next reloaded.
+pub fn set_theme(name: &str, state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    find_theme(name)?;
+
+    let config = {
+        let config = state.extension::<UserConfig>()?;
+        let mut config = config.borrow_mut();
+        config.theme = name.to_string();
+        config.clone()
+    };
+
+    apply_style(&config, state, x)
+}
+
+/// Switch to the next of the [available_themes], in name order.
+pub fn next_theme(state: &mut State<RustConn>, x: &RustConn) -> Result<()> {
+    let current = state.extension::<UserConfig>()?.borrow().theme.clone();
+    let names: Vec<String> = available_themes().into_keys().collect();
+    let next = names
+        .iter()
+        .position(|name| *name == current)
+        .map_or(0, |ix| (ix + 1) % names.len());
+
+    set_theme(&names[next], state, x)
+}